@@ -0,0 +1,476 @@
+use std::{path::PathBuf, str::FromStr, sync::Mutex};
+
+use nbdkit::{debug, parse_bool, parse_size, Error, Result};
+
+use crate::nn_backend::{
+    checksums::ReadCheck,
+    model::{Activation, ModelConfig, OutputHead},
+    renderer::RenderMode,
+    snapshots,
+    trainer::{ReplaySampling, MAX_REPLAY_RATIO},
+};
+
+// Size of the drive when no size= parameter is given, kept small since every write retrains the whole address space
+pub const DEFAULT_SIZE: u64 = 1024 * 1024;
+
+// Pending bytes that make a write train them in on the spot rather than waiting for a flush
+pub const DEFAULT_WRITEBACK: u64 = 1024 * 1024;
+// Seconds between background training runs over whatever is pending
+pub const DEFAULT_WRITEBACK_INTERVAL: u64 = 30;
+// Seconds between background scrubs of the written blocks
+pub const DEFAULT_SCRUB_INTERVAL: u64 = 300;
+
+// Name the empty export is stored under when serving a directory of drives
+pub const DEFAULT_EXPORT: &str = "default";
+
+// Training artifacts go in this subdirectory of a saved drive unless artifacts= says otherwise
+pub const ARTIFACT_SUBDIR: &str = "artifacts";
+
+static CONFIG: Mutex<DriveConfig> = Mutex::new(DriveConfig::new());
+
+#[derive(Clone, Debug)]
+pub struct DriveConfig {
+    // Size of the virtual disk in bytes
+    pub size: u64,
+    // Directory the trained weights are loaded from and saved to, None keeps the drive in memory only
+    pub model: Option<PathBuf>,
+    // Directory holding one saved drive per export, each in a subdirectory named after it
+    pub dir: Option<PathBuf>,
+    // Directory training checkpoints and logs go in, split up per export when dir= is set
+    pub artifacts: Option<PathBuf>,
+    // How many epoch checkpoints each training run keeps, None leaves the saved drive's setting alone
+    pub checkpoints: Option<usize>,
+    // Most epochs a training run may take, None leaves the saved drive's setting alone
+    pub epochs: Option<usize>,
+    // Training stops once the loss drops below this
+    pub loss_threshold: Option<f32>,
+    // Longest a training run may take in seconds
+    pub train_timeout: Option<u64>,
+    // Replayed addresses per dirty byte, setting it trains incrementally instead of retraining the whole drive
+    pub replay: Option<f64>,
+    // How replayed addresses are picked
+    pub replay_sampling: Option<ReplaySampling>,
+    // Widths of the hidden layers of new drives, saved drives keep the architecture they were trained with
+    pub hidden: Option<Vec<usize>>,
+    // Number of hidden layers of new drives when their widths aren't given
+    pub depth: Option<usize>,
+    pub activation: Option<Activation>,
+    // Skip connections around the hidden layers of new drives
+    pub residual: Option<bool>,
+    // Dropout between the hidden layers of new drives while training
+    pub dropout: Option<f64>,
+    // How new drives turn their last layer into a byte
+    pub head: OutputHead,
+    // Keep a table of bytes the weights get wrong so reads are always exact
+    pub corrections: bool,
+    // Bytes of writes to buffer before training them in, 0 trains every write as it arrives
+    pub writeback: u64,
+    // Seconds between background training runs, 0 leaves it to flushes and the threshold
+    pub writeback_interval: u64,
+    // Journal every write to disk before acknowledging it, so a crash before the next save loses nothing
+    pub journal: bool,
+    // Seconds between background scrubs, 0 turns the scrubber off
+    pub scrub_interval: u64,
+    // How training runs report their progress, the TUI only makes sense in the foreground on a terminal
+    pub progress: RenderMode,
+    // What reads do about blocks that don't match their checksum
+    pub read_check: ReadCheck,
+    // Export, or export@snapshot, that exports which don't exist yet start out as a clone of
+    pub clone_from: Option<String>,
+    // Snapshot to take of every saved drive as it's first opened, before anything is written to it
+    pub snapshot: Option<String>,
+    // Snapshot to roll every saved drive back to as it's first opened
+    pub rollback: Option<String>,
+    // Snapshot to delete from every saved drive as it's first opened
+    pub delete_snapshot: Option<String>,
+    // Serve every connection from the saved weights on an inference backend, writes fail with EROFS
+    pub readonly: bool,
+}
+
+impl DriveConfig {
+    pub const fn new() -> Self {
+        Self {
+            size: DEFAULT_SIZE,
+            model: None,
+            dir: None,
+            artifacts: None,
+            checkpoints: None,
+            epochs: None,
+            loss_threshold: None,
+            train_timeout: None,
+            replay: None,
+            replay_sampling: None,
+            hidden: None,
+            depth: None,
+            activation: None,
+            residual: None,
+            dropout: None,
+            head: OutputHead::Sigmoid,
+            corrections: false,
+            writeback: DEFAULT_WRITEBACK,
+            writeback_interval: DEFAULT_WRITEBACK_INTERVAL,
+            journal: true,
+            scrub_interval: DEFAULT_SCRUB_INTERVAL,
+            read_check: ReadCheck::Strict,
+            clone_from: None,
+            snapshot: None,
+            rollback: None,
+            delete_snapshot: None,
+            readonly: false,
+            progress: RenderMode::Log(log_debug),
+        }
+    }
+
+    pub fn get() -> Self {
+        CONFIG.lock().unwrap().clone()
+    }
+
+    pub fn set(key: &str, value: &str) -> Result<()> {
+        let mut config = CONFIG.lock().unwrap();
+        match key {
+            "size" => {
+                config.size = parse_size(value)? as u64;
+                Ok(())
+            }
+            "model" => {
+                config.model = Some(PathBuf::from(value));
+                Ok(())
+            }
+            "dir" => {
+                config.dir = Some(PathBuf::from(value));
+                Ok(())
+            }
+            "artifacts" => {
+                config.artifacts = Some(PathBuf::from(value));
+                Ok(())
+            }
+            "checkpoints" => {
+                config.checkpoints = Some(parse_number(key, value)?);
+                Ok(())
+            }
+            "epochs" => {
+                config.epochs = Some(parse_number(key, value)?);
+                Ok(())
+            }
+            "loss_threshold" => {
+                config.loss_threshold = Some(parse_number(key, value)?);
+                Ok(())
+            }
+            "train_timeout" => {
+                config.train_timeout = Some(parse_number(key, value)?);
+                Ok(())
+            }
+            "replay" => {
+                config.replay = Some(parse_number(key, value)?);
+                Ok(())
+            }
+            "replay_sampling" => {
+                config.replay_sampling = Some(match value {
+                    "uniform" => ReplaySampling::Uniform,
+                    "recency" => ReplaySampling::Recency,
+                    "error" => ReplaySampling::Error,
+                    _ => {
+                        return Err(Error::new(
+                            libc::EINVAL,
+                            format!(
+                                "replay_sampling must be uniform, recency or error: {}",
+                                value
+                            ),
+                        ))
+                    }
+                });
+                Ok(())
+            }
+            "hidden" => {
+                config.hidden = Some(
+                    value
+                        .split(',')
+                        .map(|width| parse_number(key, width.trim()))
+                        .collect::<Result<_>>()?,
+                );
+                Ok(())
+            }
+            "depth" => {
+                config.depth = Some(parse_number(key, value)?);
+                Ok(())
+            }
+            "activation" => {
+                config.activation = Some(match value {
+                    "relu" => Activation::Relu,
+                    "gelu" => Activation::Gelu,
+                    "silu" => Activation::Silu,
+                    "sine" => Activation::Sine,
+                    _ => {
+                        return Err(Error::new(
+                            libc::EINVAL,
+                            format!("activation must be relu, gelu, silu or sine: {}", value),
+                        ))
+                    }
+                });
+                Ok(())
+            }
+            "residual" => {
+                config.residual = Some(parse_bool(value)?);
+                Ok(())
+            }
+            "dropout" => {
+                config.dropout = Some(parse_number(key, value)?);
+                Ok(())
+            }
+            "head" => {
+                config.head = match value {
+                    "relu" => OutputHead::Relu,
+                    "sigmoid" => OutputHead::Sigmoid,
+                    "clamped" => OutputHead::Clamped,
+                    "bits" => OutputHead::Bits,
+                    "classes" => OutputHead::Classes,
+                    _ => {
+                        return Err(Error::new(
+                            libc::EINVAL,
+                            format!(
+                                "head must be relu, sigmoid, clamped, bits or classes: {}",
+                                value
+                            ),
+                        ))
+                    }
+                };
+                Ok(())
+            }
+            "corrections" => {
+                config.corrections = parse_bool(value)?;
+                Ok(())
+            }
+            "journal" => {
+                config.journal = parse_bool(value)?;
+                Ok(())
+            }
+            "progress" => {
+                config.progress = match value {
+                    "quiet" => RenderMode::Quiet,
+                    "log" => RenderMode::Log(log_debug),
+                    "tui" => RenderMode::Tui,
+                    _ => {
+                        return Err(Error::new(
+                            libc::EINVAL,
+                            format!("progress must be quiet, log or tui: {}", value),
+                        ))
+                    }
+                };
+                Ok(())
+            }
+            "read_check" => {
+                config.read_check = match value {
+                    "strict" => ReadCheck::Strict,
+                    "best_effort" => ReadCheck::BestEffort(log_debug),
+                    "off" => ReadCheck::Off,
+                    _ => {
+                        return Err(Error::new(
+                            libc::EINVAL,
+                            format!("read_check must be strict, best_effort or off: {}", value),
+                        ))
+                    }
+                };
+                Ok(())
+            }
+            "clone_from" => {
+                config.clone_from = Some(value.to_string());
+                Ok(())
+            }
+            "snapshot" => {
+                config.snapshot = Some(value.to_string());
+                Ok(())
+            }
+            "rollback" => {
+                config.rollback = Some(value.to_string());
+                Ok(())
+            }
+            "delete_snapshot" => {
+                config.delete_snapshot = Some(value.to_string());
+                Ok(())
+            }
+            "readonly" => {
+                config.readonly = parse_bool(value)?;
+                Ok(())
+            }
+            "writeback" => {
+                config.writeback = parse_size(value)? as u64;
+                Ok(())
+            }
+            "scrub_interval" => {
+                config.scrub_interval = parse_number(key, value)?;
+                Ok(())
+            }
+            "writeback_interval" => {
+                config.writeback_interval = parse_number(key, value)?;
+                Ok(())
+            }
+            _ => Err(Error::new(
+                libc::EINVAL,
+                format!("Unknown parameter: {}", key),
+            )),
+        }
+    }
+
+    // Where the drive behind an export is saved, every export shares the one drive unless dir= is set
+    pub fn model_dir(&self, export: &str) -> Result<Option<PathBuf>> {
+        match &self.dir {
+            Some(dir) => Ok(Some(dir.join(export_dir_name(export)?))),
+            None => Ok(self.model.clone()),
+        }
+    }
+
+    // Where training keeps its checkpoints and logs for an export, None leaves it to a scratch directory
+    pub fn artifact_dir(&self, export: &str) -> Result<Option<PathBuf>> {
+        match (&self.artifacts, &self.dir) {
+            (Some(artifacts), Some(_)) => Ok(Some(artifacts.join(export_dir_name(export)?))),
+            (Some(artifacts), None) => Ok(Some(artifacts.clone())),
+            (None, _) => Ok(self
+                .model_dir(export)?
+                .map(|model_dir| model_dir.join(ARTIFACT_SUBDIR))),
+        }
+    }
+
+    // Where new exports are cloned from, a snapshot's directory when clone_from names one
+    pub fn clone_dir(&self) -> Result<Option<PathBuf>> {
+        let Some(clone_from) = &self.clone_from else {
+            return Ok(None);
+        };
+        let (export, snapshot) = split_snapshot(clone_from);
+        let Some(model_dir) = self.model_dir(export)? else {
+            return Ok(None);
+        };
+        match snapshot {
+            Some(snapshot) => Ok(Some(snapshots::path(&model_dir, snapshot)?)),
+            None => Ok(Some(model_dir)),
+        }
+    }
+
+    // What a brand new drive's network looks like, the input is one feature per address bit
+    pub fn model_config(&self) -> ModelConfig {
+        ModelConfig::new(64, 1)
+            .with_hidden(self.hidden.clone())
+            .with_depth(self.depth)
+            .with_activation(self.activation)
+            .with_residual(self.residual)
+            .with_dropout(self.dropout)
+            .with_head(Some(self.head))
+    }
+
+    pub fn validate() -> Result<()> {
+        let config = CONFIG.lock().unwrap();
+        if config.size == 0 {
+            return Err(Error::new(libc::EINVAL, "size must be greater than 0"));
+        }
+        if usize::try_from(config.size).is_err() {
+            return Err(Error::new(
+                libc::EINVAL,
+                format!(
+                    "size {} does not fit in this platform's address space",
+                    config.size
+                ),
+            ));
+        }
+        if config.model.is_some() && config.dir.is_some() {
+            return Err(Error::new(
+                libc::EINVAL,
+                "model and dir can't be used together",
+            ));
+        }
+        if config.clone_from.is_some() && config.dir.is_none() {
+            return Err(Error::new(libc::EINVAL, "clone_from needs dir"));
+        }
+        if config.model.is_none()
+            && config.dir.is_none()
+            && (config.snapshot.is_some()
+                || config.rollback.is_some()
+                || config.delete_snapshot.is_some())
+        {
+            return Err(Error::new(
+                libc::EINVAL,
+                "snapshot, rollback and delete_snapshot need model or dir",
+            ));
+        }
+        if config
+            .replay
+            .is_some_and(|replay| !(0.0..=MAX_REPLAY_RATIO).contains(&replay))
+        {
+            return Err(Error::new(
+                libc::EINVAL,
+                format!("replay must be between 0 and {}", MAX_REPLAY_RATIO),
+            ));
+        }
+        if config.replay_sampling == Some(ReplaySampling::Error) && !config.corrections {
+            return Err(Error::new(
+                libc::EINVAL,
+                "replay_sampling=error needs corrections",
+            ));
+        }
+        if config
+            .hidden
+            .as_ref()
+            .is_some_and(|hidden| hidden.contains(&0))
+        {
+            return Err(Error::new(
+                libc::EINVAL,
+                "hidden layers must be at least 1 wide",
+            ));
+        }
+        if config
+            .dropout
+            .is_some_and(|dropout| !(0.0..1.0).contains(&dropout))
+        {
+            return Err(Error::new(
+                libc::EINVAL,
+                "dropout must be at least 0 and less than 1",
+            ));
+        }
+        if usize::try_from(config.writeback).is_err() {
+            return Err(Error::new(
+                libc::EINVAL,
+                format!(
+                    "writeback {} does not fit in this platform's address space",
+                    config.writeback
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn parse_number<T: FromStr>(key: &str, value: &str) -> Result<T> {
+    value
+        .parse()
+        .map_err(|_| Error::new(libc::EINVAL, format!("{} must be a number: {}", key, value)))
+}
+
+// Splits an export name of the form export@snapshot into the export and the snapshot it's to be read at
+pub fn split_snapshot(export: &str) -> (&str, Option<&str>) {
+    match export.rsplit_once('@') {
+        Some((export, snapshot)) => (export, Some(snapshot)),
+        None => (export, None),
+    }
+}
+
+// Where training progress and best-effort checksum mismatches go, nbdkit's debug output
+fn log_debug(line: &str) {
+    debug!("{}", line);
+}
+
+fn export_dir_name(export: &str) -> Result<&str> {
+    if export.is_empty() {
+        return Ok(DEFAULT_EXPORT);
+    }
+    if export.starts_with('.') || export.contains(['/', '\\']) {
+        return Err(Error::new(
+            libc::EINVAL,
+            format!("Export name can't be used as a directory name: {}", export),
+        ));
+    }
+    Ok(export)
+}
+
+impl Default for DriveConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,22 +1,418 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, Weak},
+    thread,
+    time::Duration,
+};
+
 use burn::backend::Autodiff;
-#[allow(unused_variables)]
-use burn::backend::{wgpu::AutoGraphicsApi, Wgpu};
+mod config;
 mod nn_backend;
-use interface::TheNetwork;
+use config::DriveConfig;
+use interface::{FrozenNetwork, NNError, TheNetwork, TrainReport};
 use nbdkit::*;
 use nn_backend::*;
-use trainer::TrainingConfig;
 
-type NetworkClamped = TheNetwork<Autodiff<Wgpu<AutoGraphicsApi, f32, i32>>>;
+#[cfg(feature = "backend-wgpu")]
+type DriveBackend = burn::backend::Wgpu<burn::backend::wgpu::AutoGraphicsApi, f32, i32>;
+#[cfg(all(feature = "backend-candle", not(feature = "backend-wgpu")))]
+type DriveBackend = burn::backend::Candle<f32, i64>;
+#[cfg(all(
+    feature = "backend-ndarray",
+    not(any(feature = "backend-wgpu", feature = "backend-candle"))
+))]
+type DriveBackend = burn::backend::NdArray<f32>;
+#[cfg(not(any(
+    feature = "backend-ndarray",
+    feature = "backend-wgpu",
+    feature = "backend-candle"
+)))]
+compile_error!(
+    "One of the backend-ndarray, backend-wgpu or backend-candle features must be enabled"
+);
+
+type NetworkClamped = TheNetwork<Autodiff<DriveBackend>>;
+type NetworkFrozen = FrozenNetwork<DriveBackend>;
+
+// Connections to the same export share one network so they all see the same drive, built by whichever connection opens it first
+static NETWORKS: Mutex<BTreeMap<Option<PathBuf>, Arc<NetworkClamped>>> =
+    Mutex::new(BTreeMap::new());
+
+fn shared_network(
+    config: &DriveConfig,
+    model_dir: Option<&Path>,
+    artifact_dir: Option<&Path>,
+) -> Result<Arc<NetworkClamped>> {
+    let key = model_dir.map(Path::to_path_buf);
+    if let Some(storage_network) = NETWORKS.lock().unwrap().get(&key) {
+        return Ok(storage_network.clone());
+    }
+    // Cloning can mean training the parent in first, so it's done without holding up every other open
+    if let (Some(model_dir), Some(parent_dir)) = (model_dir, config.clone_dir()?) {
+        if !model_dir.exists() && model_dir != parent_dir {
+            clone_drive(&parent_dir, model_dir)?;
+        }
+    }
+    let mut networks = NETWORKS.lock().unwrap();
+    // Another connection may have opened it while this one was cloning
+    if let Some(storage_network) = networks.get(&key) {
+        return Ok(storage_network.clone());
+    }
+    if let Some(model_dir) = model_dir.filter(|model_dir| model_dir.exists()) {
+        manage_snapshots(config, model_dir)?;
+    }
+    // An export that hasn't been saved yet starts out blank and only reaches the disk once something is written to it
+    let mut storage_network = match model_dir {
+        Some(model_dir) => {
+            NetworkClamped::open(model_dir, config.size as usize, config.model_config())?
+        }
+        None => NetworkClamped::init(config.size as usize, config.model_config()),
+    };
+    if config.corrections {
+        storage_network = storage_network.with_corrections();
+    }
+    if let Some(model_dir) = model_dir {
+        storage_network = if config.journal {
+            storage_network.with_journal(model_dir)?
+        } else {
+            storage_network.with_leftover_journal(model_dir)?
+        };
+        if storage_network.dirty_bytes() > 0 {
+            debug!(
+                "replayed the journal | dirty_bytes={}",
+                storage_network.dirty_bytes()
+            );
+        }
+    }
+    storage_network = storage_network
+        .with_render_mode(config.progress)
+        .with_read_check(config.read_check);
+    if let Some(artifact_dir) = artifact_dir {
+        storage_network = storage_network.with_artifact_dir(artifact_dir);
+    }
+    if let Some(checkpoints) = config.checkpoints {
+        storage_network = storage_network.with_checkpoints(checkpoints);
+    }
+    if let Some(epochs) = config.epochs {
+        storage_network = storage_network.with_max_epochs(epochs);
+    }
+    if let Some(loss_threshold) = config.loss_threshold {
+        storage_network = storage_network.with_loss_threshold(loss_threshold);
+    }
+    if let Some(train_timeout) = config.train_timeout {
+        storage_network = storage_network.with_time_limit(train_timeout);
+    }
+    if let Some(replay) = config.replay {
+        storage_network = storage_network.with_replay_ratio(replay);
+    }
+    if let Some(replay_sampling) = config.replay_sampling {
+        storage_network = storage_network.with_replay_sampling(replay_sampling);
+    }
+    let storage_network =
+        Arc::new(storage_network.with_writeback_threshold(config.writeback as usize));
+    if config.writeback_interval > 0 {
+        spawn_timer(
+            Arc::downgrade(&storage_network),
+            Duration::from_secs(config.writeback_interval),
+            writeback,
+        );
+    }
+    if config.scrub_interval > 0 {
+        spawn_timer(
+            Arc::downgrade(&storage_network),
+            Duration::from_secs(config.scrub_interval),
+            scrub,
+        );
+    }
+    networks.insert(key, storage_network.clone());
+    Ok(storage_network)
+}
+
+// Opens serialize on this while they clone, so two of them can't both fork into the same new export
+static CLONING: Mutex<()> = Mutex::new(());
+
+// Trains in and saves everything the parent has acknowledged so far, then starts the new export off it
+fn clone_drive(parent_dir: &Path, model_dir: &Path) -> Result<()> {
+    if !parent_dir.is_dir() {
+        return Err(nbdkit::Error::new(
+            libc::ENOENT,
+            format!("Nothing to clone at {}", parent_dir.display()),
+        ));
+    }
+    let parent = NETWORKS
+        .lock()
+        .unwrap()
+        .get(&Some(parent_dir.to_path_buf()))
+        .cloned();
+    if let Some(parent) = parent {
+        if let Some(report) = parent.commit()? {
+            debug!(
+                "trained the parent's pending writes before cloning | {}",
+                report
+            );
+            check_report(&report)?;
+        }
+        parent.save(parent_dir)?;
+        forget_frozen(parent_dir);
+    }
+    let _cloning = CLONING.lock().unwrap();
+    if model_dir.exists() {
+        return Ok(());
+    }
+    snapshots::fork(parent_dir, model_dir)?;
+    debug!(
+        "cloned the drive | from={} to={}",
+        parent_dir.display(),
+        model_dir.display()
+    );
+    Ok(())
+}
+
+// Snapshot housekeeping asked for on the command line, done before the drive is loaded so a rollback is what gets served
+fn manage_snapshots(config: &DriveConfig, model_dir: &Path) -> Result<()> {
+    if let Some(name) = &config.snapshot {
+        // Restarting with the same parameters keeps the snapshot the first start took
+        if snapshots::path(model_dir, name)?.exists() {
+            debug!("snapshot already exists, keeping it | name={}", name);
+        } else {
+            snapshots::create(model_dir, name)?;
+            forget_frozen(&snapshots::path(model_dir, name)?);
+            debug!("took snapshot | name={} dir={}", name, model_dir.display());
+        }
+    }
+    if let Some(name) = &config.rollback {
+        // Restarting with the same parameters keeps what was written since the first start rolled back
+        if snapshots::rollback(model_dir, name)? {
+            forget_frozen(model_dir);
+            debug!("rolled back | name={} dir={}", name, model_dir.display());
+        } else {
+            debug!("already rolled back, keeping it | name={}", name);
+        }
+    }
+    if let Some(name) = &config.delete_snapshot {
+        if snapshots::path(model_dir, name)?.exists() {
+            snapshots::delete(model_dir, name)?;
+            debug!(
+                "deleted snapshot | name={} dir={}",
+                name,
+                model_dir.display()
+            );
+        }
+    }
+    let names = snapshots::list(model_dir)?;
+    if !names.is_empty() {
+        debug!(
+            "snapshots | dir={} names={}",
+            model_dir.display(),
+            names.join(",")
+        );
+    }
+    Ok(())
+}
+
+// Readonly connections share their own copy of whatever was saved when they opened, they never see writes that are still pending
+static FROZEN: Mutex<BTreeMap<Option<PathBuf>, Arc<NetworkFrozen>>> = Mutex::new(BTreeMap::new());
+
+fn shared_frozen(config: &DriveConfig, model_dir: Option<&Path>) -> Result<Arc<NetworkFrozen>> {
+    let mut frozen = FROZEN.lock().unwrap();
+    let key = model_dir.map(Path::to_path_buf);
+    if let Some(storage_network) = frozen.get(&key) {
+        return Ok(storage_network.clone());
+    }
+    let storage_network = Arc::new(
+        NetworkFrozen::open(model_dir, config.size as usize)?.with_read_check(config.read_check),
+    );
+    frozen.insert(key, storage_network.clone());
+    Ok(storage_network)
+}
+
+// Called whenever a saved drive changes on disk, readonly connections opened after that load it afresh while those already open keep the copy they have
+fn forget_frozen(model_dir: &Path) {
+    FROZEN
+        .lock()
+        .unwrap()
+        .remove(&Some(model_dir.to_path_buf()));
+}
+
+// Runs a task against the network every so often in the background, stops once the network is gone
+fn spawn_timer(
+    storage_network: Weak<NetworkClamped>,
+    interval: Duration,
+    task: fn(&NetworkClamped),
+) {
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        let Some(storage_network) = storage_network.upgrade() else {
+            return;
+        };
+        task(&storage_network);
+    });
+}
+
+// Trains pending writes in so they don't sit in memory until the next flush
+fn writeback(storage_network: &NetworkClamped) {
+    if storage_network.dirty_bytes() == 0 {
+        return;
+    }
+    match storage_network.commit() {
+        Ok(Some(report)) => debug!("background writeback | {}", report),
+        Ok(None) => {}
+        Err(e) => debug!(
+            "background writeback failed, the writes stay pending | {}",
+            e
+        ),
+    }
+}
+
+// Looks for blocks that training elsewhere has corrupted and puts them back
+fn scrub(storage_network: &NetworkClamped) {
+    match storage_network.scrub() {
+        Ok(report) => {
+            let totals = storage_network.scrub_totals();
+            debug!(
+                "scrubbed | {} total_drifted={} total_repaired={} total_lost={}",
+                report, totals.drifted, totals.repaired, totals.lost
+            );
+        }
+        Err(e) => debug!("scrub failed | {}", e),
+    }
+}
+
+// Every pending byte has to read back, either from the weights or from the correction table
+fn check_report(report: &TrainReport) -> Result<()> {
+    let verified = report.verified_bytes + report.corrected_bytes;
+    if verified != report.trained_bytes {
+        return Err(NNError::VerifyMismatch {
+            verified,
+            expected: report.trained_bytes,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+impl From<NNError> for nbdkit::Error {
+    fn from(e: NNError) -> Self {
+        // nbdkit only logs the message, so whatever caused the error is spelled out in it too
+        let mut message = e.to_string();
+        let mut cause = std::error::Error::source(&e);
+        while let Some(error) = cause {
+            message.push_str(&format!(": {}", error));
+            cause = error.source();
+        }
+        nbdkit::Error::new(e.errno(), message)
+    }
+}
+
+enum Storage {
+    Trainable(Arc<NetworkClamped>),
+    Frozen(Arc<NetworkFrozen>),
+}
+
 #[derive()]
 struct MyDrive {
-    storage_network: NetworkClamped,
+    storage: Storage,
+    model_dir: Option<PathBuf>,
 }
 
-impl Default for MyDrive {
-    fn default() -> Self {
-        Self {
-            storage_network: NetworkClamped::init(),
+impl MyDrive {
+    fn new(config: &DriveConfig, export: &str, readonly: bool) -> Result<Self> {
+        let (export, snapshot) = config::split_snapshot(export);
+        let model_dir = config.model_dir(export)?;
+        if let Some(snapshot) = snapshot {
+            return Self::at_snapshot(config, model_dir, snapshot);
+        }
+        let storage = if readonly {
+            // There's nothing to serve from an export nobody has written to yet
+            if config.dir.is_some() && !model_dir.as_deref().is_some_and(Path::exists) {
+                return Err(nbdkit::Error::new(
+                    libc::ENOENT,
+                    format!("No such export: {}", export),
+                ));
+            }
+            Storage::Frozen(shared_frozen(config, model_dir.as_deref())?)
+        } else {
+            let artifact_dir = config.artifact_dir(export)?;
+            Storage::Trainable(shared_network(
+                config,
+                model_dir.as_deref(),
+                artifact_dir.as_deref(),
+            )?)
+        };
+        Ok(Self { storage, model_dir })
+    }
+
+    // Snapshots are only ever read, whatever the connection asked for
+    fn at_snapshot(config: &DriveConfig, model_dir: Option<PathBuf>, name: &str) -> Result<Self> {
+        let Some(model_dir) = model_dir else {
+            return Err(nbdkit::Error::new(
+                libc::EINVAL,
+                "Snapshots need model or dir",
+            ));
+        };
+        let snapshot_dir = snapshots::path(&model_dir, name)?;
+        if !snapshot_dir.is_dir() {
+            return Err(nbdkit::Error::new(
+                libc::ENOENT,
+                format!("No such snapshot: {}", name),
+            ));
+        }
+        Ok(Self {
+            storage: Storage::Frozen(shared_frozen(config, Some(&snapshot_dir))?),
+            model_dir: Some(snapshot_dir),
+        })
+    }
+
+    fn storage_network(&self) -> Result<&NetworkClamped> {
+        match &self.storage {
+            Storage::Trainable(storage_network) => Ok(storage_network),
+            Storage::Frozen(_) => Err(nbdkit::Error::new(
+                libc::EROFS,
+                "The drive is open read-only",
+            )),
+        }
+    }
+
+    // Trains in whatever is pending, then saves the result
+    fn commit(&self) -> Result<()> {
+        let Storage::Trainable(storage_network) = &self.storage else {
+            return Ok(());
+        };
+        if let Some(report) = storage_network.commit()? {
+            debug!(
+                "trained pending writes | {} written_bytes={} corrections_stored={}",
+                report,
+                storage_network.written_bytes(),
+                storage_network.correction_count()
+            );
+            check_report(&report)?;
+        }
+        self.save()
+    }
+
+    fn save(&self) -> Result<()> {
+        match &self.model_dir {
+            Some(model_dir) => {
+                let storage_network = self.storage_network()?;
+                // Exports are only created once they hold something
+                if !model_dir.exists() && storage_network.written_bytes() == 0 {
+                    return Ok(());
+                }
+                storage_network.save(model_dir)?;
+                forget_frozen(model_dir);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for MyDrive {
+    fn drop(&mut self) {
+        // nbdkit closes the handle by dropping it, so this is the last chance to keep what was written
+        if let Err(e) = self.commit() {
+            debug!("failed to save the drive on close | {}", e);
         }
     }
 }
@@ -25,28 +421,220 @@ impl Server for MyDrive {
     fn name() -> &'static str {
         "The-worlds-first-functional-drive"
     }
-    fn open(_readonly: bool) -> Result<Box<dyn Server>> {
-        debug!("booting the drive | readonly={}", _readonly);
-        Ok(Box::<MyDrive>::default())
+
+    fn config(key: &str, value: &str) -> Result<()> {
+        DriveConfig::set(key, value)
+    }
+
+    fn config_complete() -> Result<()> {
+        DriveConfig::validate()
+    }
+
+    fn config_help() -> Option<&'static str> {
+        Some(
+            "size=<SIZE>    Size of new virtual disks (eg. 64M, 1G), defaults to 1M\n\
+             model=<DIR>    Directory to load trained weights from and save them to\n\
+             dir=<DIR>    Directory with one saved drive per export name, new exports are made on first write\n\
+             artifacts=<DIR>    Directory for training checkpoints and logs, defaults to an artifacts directory in the saved drive\n\
+             checkpoints=<N>    Epoch checkpoints each training run keeps, 0 turns them off, defaults to 2\n\
+             epochs=<N>    Most epochs a training run may take, it stops sooner once every byte reads back, defaults to 20\n\
+             loss_threshold=<LOSS>    Also stop training once the loss drops below this\n\
+             train_timeout=<SECS>    Also stop training once it has run this long\n\
+             replay=<RATIO>    Train only on new writes plus this many replayed old bytes per written byte, at most 64\n\
+             replay_sampling=uniform|recency|error    How replayed bytes are picked, error needs corrections, defaults to uniform\n\
+             hidden=<W,W,...>    Widths of the hidden layers of new drives, defaults to 32,128,128,128,128,512,128\n\
+             depth=<N>    Number of 128 wide hidden layers of new drives when hidden isn't given\n\
+             activation=relu|gelu|silu|sine    Activation between the hidden layers of new drives, defaults to relu\n\
+             residual=<BOOL>    Skip connections around the hidden layers of new drives where the widths match\n\
+             dropout=<P>    Dropout between the hidden layers of new drives while training, defaults to 0\n\
+             head=relu|sigmoid|clamped|bits|classes    How new drives turn their last layer into a byte, classes trains 256 logits with cross-entropy, defaults to sigmoid\n\
+             corrections=<BOOL>    Record bytes the network gets wrong so reads are exact\n\
+             writeback=<SIZE>    Buffer this many bytes of writes before training them in, defaults to 1M\n\
+             writeback_interval=<SECS>    Train buffered writes in every so often, 0 disables, defaults to 30\n\
+             journal=<BOOL>    Journal writes to disk before acknowledging them so they survive a crash, defaults to true, a journal an earlier run left is replayed either way\n\
+             progress=quiet|log|tui    How training reports progress, log sends it to debug output and is the default\n\
+             read_check=strict|best_effort|off    What reads do about blocks that fail their checksum, strict fails them with EIO and is the default\n\
+             scrub_interval=<SECS>    Check written blocks against their checksums and repair drift this often, 0 disables, defaults to 300\n\
+             clone_from=<EXPORT>[@<NAME>]    Exports that don't exist yet start as a copy-on-write clone of this export or one of its snapshots\n\
+             snapshot=<NAME>    Snapshot each saved drive's weights and side tables as it's first opened, open export@NAME to read it back\n\
+             rollback=<NAME>    Roll each saved drive back to a snapshot as it's first opened, writes since are lost, only done once per snapshot\n\
+             delete_snapshot=<NAME>    Delete a snapshot from each saved drive as it's first opened\n\
+             readonly=<BOOL>    Serve the saved weights without ever training them",
+        )
+    }
+
+    fn thread_model() -> Result<ThreadModel> {
+        // TheNetwork does its own locking, reads never wait on each other and writes queue up behind one another
+        Ok(ThreadModel::Parallel)
+    }
+
+    fn open(readonly: bool) -> Result<Box<dyn Server>> {
+        let config = DriveConfig::get();
+        let readonly = readonly || config.readonly;
+        // The bindings have no list_exports or default_export, so clients have to know the name of the export they want
+        let export = export_name().unwrap_or_default();
+        debug!(
+            "booting the drive | export={:?} readonly={}",
+            export, readonly
+        );
+        Ok(Box::new(MyDrive::new(&config, &export, readonly)?))
+    }
+
+    fn can_write(&self) -> Result<bool> {
+        Ok(matches!(self.storage, Storage::Trainable(_)))
     }
 
     fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<()> {
-        match self.storage_network.read_at(buf, offset) {
-            Ok(_) => Ok(()),
-            Err(_) => Err(nbdkit::Error::new(0, "Something has gone wrong")),
+        match &self.storage {
+            Storage::Trainable(storage_network) => Ok(storage_network.read_at(buf, offset)?),
+            Storage::Frozen(storage_network) => Ok(storage_network.read_at(buf, offset)?),
         }
     }
-    fn write_at(&self, buf: &[u8], offset: u64, _flags: Flags) -> Result<()> {
-        match self.storage_network.train(buf, offset) {
-            Ok(_) => Ok(()),
-            Err(_) => Err(nbdkit::Error::new(0, "Something went wrong writing")),
+    fn write_at(&self, buf: &[u8], offset: u64, flags: Flags) -> Result<()> {
+        let storage_network = self.storage_network()?;
+        if let Some(report) = storage_network.write(buf, offset)? {
+            debug!(
+                "trained pending writes | offset={} {} written_bytes={} corrections_stored={}",
+                offset,
+                report,
+                storage_network.written_bytes(),
+                storage_network.correction_count()
+            );
+            check_report(&report)?;
         }
-        // self.nn_train(burn::backend::wgpu::WgpuDevice::default())
+        if flags.contains(Flags::FUA) {
+            // The write has to be in the weights, reading back and on disk before it's acknowledged
+            self.commit()?;
+            let mut read_back = vec![0u8; buf.len()];
+            storage_network.read_at(&mut read_back, offset)?;
+            let verified = read_back
+                .iter()
+                .zip(buf.iter())
+                .filter(|(read, written)| read == written)
+                .count();
+            if verified != buf.len() {
+                return Err(NNError::VerifyMismatch {
+                    verified,
+                    expected: buf.len(),
+                }
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    fn can_fua(&self) -> Result<FuaFlags> {
+        Ok(FuaFlags::Native)
+    }
+
+    fn can_flush(&self) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.commit()
+    }
+
+    fn can_trim(&self) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn trim(&self, count: u32, offset: u64, flags: Flags) -> Result<()> {
+        self.storage_network()?.forget(count as usize, offset)?;
+        debug!("trimmed | offset={} count={}", offset, count);
+        if flags.contains(Flags::FUA) {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    fn can_zero(&self) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn can_fast_zero(&self) -> Result<bool> {
+        // Zeroing is a write like any other and ends up in a training run
+        Ok(false)
+    }
+
+    fn zero(&self, count: u32, offset: u64, flags: Flags) -> Result<()> {
+        if flags.contains(Flags::FAST_ZERO) {
+            return Err(nbdkit::Error::new(
+                libc::ENOTSUP,
+                "Zeroing trains the network so it is never fast",
+            ));
+        }
+        self.write_at(&vec![0u8; count as usize], offset, flags)
+    }
+
+    fn can_extents(&self) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn extents(
+        &self,
+        count: u32,
+        offset: u64,
+        flags: Flags,
+        extent_handle: &mut ExtentHandle,
+    ) -> Result<()> {
+        let extents = match &self.storage {
+            Storage::Trainable(storage_network) => {
+                storage_network.extents(count as usize, offset)?
+            }
+            Storage::Frozen(storage_network) => storage_network.extents(count as usize, offset)?,
+        };
+        for extent in extents {
+            let extent_type = if extent.written {
+                ExtentType::Allocated
+            } else {
+                ExtentType::HoleZero
+            };
+            extent_handle.add(extent.offset, extent.len, extent_type)?;
+            if flags.contains(Flags::REQ_ONE) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn can_cache(&self) -> Result<CacheFlags> {
+        // Only the trainable network keeps a prediction cache
+        match self.storage {
+            Storage::Trainable(_) => Ok(CacheFlags::Native),
+            Storage::Frozen(_) => Ok(CacheFlags::None),
+        }
+    }
+
+    fn cache(&self, count: u32, offset: u64) -> Result<()> {
+        Ok(self.storage_network()?.cache(count as usize, offset)?)
     }
 
     fn get_size(&self) -> Result<i64> {
-        Ok(i64::MAX)
+        match &self.storage {
+            Storage::Trainable(storage_network) => Ok(storage_network.max_size() as i64),
+            Storage::Frozen(storage_network) => Ok(storage_network.max_size() as i64),
+        }
     }
 }
 
-plugin!(MyDrive { write_at });
+plugin!(MyDrive {
+    write_at,
+    config,
+    can_write,
+    can_fua,
+    config_complete,
+    config_help,
+    can_flush,
+    flush,
+    can_trim,
+    trim,
+    can_zero,
+    can_fast_zero,
+    zero,
+    can_extents,
+    extents,
+    can_cache,
+    cache,
+    thread_model
+});
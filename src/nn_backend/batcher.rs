@@ -1,25 +1,28 @@
 use burn::{
     data::dataloader::batcher::Batcher,
-    tensor::{backend::Backend, Tensor},
+    tensor::{backend::Backend, Data, Shape, Tensor},
 };
 
-use super::dataloader::DataItem;
+use super::{dataloader::DataItem, model::OutputHead};
 
 #[derive(Clone)]
 pub struct InternalBatcher<B: Backend> {
     device: B::Device,
+    // Targets are put in whatever range this head outputs
+    head: OutputHead,
 }
 
 impl<B: Backend> InternalBatcher<B> {
-    pub fn new(device: B::Device) -> Self {
-        Self { device }
+    pub fn new(device: B::Device, head: OutputHead) -> Self {
+        Self { device, head }
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct Batch<B: Backend> {
     pub addresses: Tensor<B, 2>,
-    pub targets: Tensor<B, 1>,
+    // One row per address, as wide as the head's targets
+    pub targets: Tensor<B, 2>,
 }
 
 fn u64_to_bits(input: u64) -> [f32; 64] {
@@ -42,11 +45,14 @@ impl<B: Backend> Batcher<DataItem, Batch<B>> for InternalBatcher<B> {
         }
         let inputs = Tensor::cat(inputs, 0);
 
-        let targets = items
-            .iter()
-            .map(|v| Tensor::<B, 1>::from_floats([v.value as f32], &self.device))
-            .collect::<Vec<Tensor<B, 1>>>();
-        let targets = Tensor::cat(targets, 0).to_device(&self.device);
+        let mut targets = Vec::with_capacity(items.len() * self.head.target_width());
+        for item in items.iter() {
+            self.head.targets(item.value, &mut targets);
+        }
+        let targets = Tensor::from_data(
+            Data::new(targets, Shape::new([items.len(), self.head.target_width()])).convert(),
+            &self.device,
+        );
         Batch {
             addresses: inputs,
             targets,
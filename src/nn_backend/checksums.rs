@@ -0,0 +1,158 @@
+use std::{collections::BTreeMap, io, path::Path};
+
+use super::table;
+
+// Checksums cover blocks of this many bytes, the last block of the drive may be shorter
+pub const CHECKSUM_BLOCK: u64 = 4096;
+// Each entry is stored as a little endian u64 block number followed by a little endian u32 checksum
+const ENTRY_LEN: usize = 12;
+// CRC-32C (Castagnoli) polynomial, bit reversed
+const CRC32C_POLY: u32 = 0x82f6_3b78;
+const CRC32C_TABLE: [u32; 256] = crc32c_table();
+
+const fn crc32c_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ CRC32C_POLY
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+pub fn crc32c(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc = CRC32C_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+// What a read does about a block that doesn't match its checksum
+#[derive(Clone, Copy, Debug)]
+pub enum ReadCheck {
+    // Reads aren't checked at all
+    Off,
+    // The read fails with EIO
+    Strict,
+    // The mismatch is handed to the sink and the read returns the bytes anyway
+    BestEffort(fn(&str)),
+}
+
+// The blocks that [offset, offset + len) touches, none for an empty range even part way into a block
+pub fn blocks(len: usize, offset: u64) -> impl Iterator<Item = u64> {
+    let first = offset / CHECKSUM_BLOCK;
+    let end = match len {
+        0 => first,
+        _ => (offset + len as u64).div_ceil(CHECKSUM_BLOCK),
+    };
+    first..end
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ChecksumTable {
+    // Block number -> checksum of what the block held when it was last written
+    blocks: BTreeMap<u64, u32>,
+}
+
+impl ChecksumTable {
+    pub fn get(&self, block: u64) -> Option<u32> {
+        self.blocks.get(&block).copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u64, u32)> + '_ {
+        self.blocks
+            .iter()
+            .map(|(&block, &checksum)| (block, checksum))
+    }
+
+    pub fn record(&mut self, block: u64, checksum: u32) {
+        self.blocks.insert(block, checksum);
+    }
+
+    // Blocks that are only partly forgotten go too, they aren't checked again until they're next written
+    pub fn forget(&mut self, len: usize, offset: u64) {
+        for block in blocks(len, offset) {
+            self.blocks.remove(&block);
+        }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut blocks = BTreeMap::new();
+        table::load::<ENTRY_LEN>(path, |block, checksum| {
+            blocks.insert(block, u32::from_le_bytes(checksum.try_into().unwrap()));
+        })?;
+        Ok(Self { blocks })
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        table::save::<ENTRY_LEN, _>(
+            path,
+            self.blocks
+                .iter()
+                .map(|(&block, &checksum)| (block, checksum.to_le_bytes())),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+
+    #[test]
+    fn crc32c_matches_the_check_value() {
+        assert_eq!(crc32c(b"123456789"), 0xE306_9283);
+        assert_eq!(crc32c(&[]), 0);
+    }
+
+    #[test]
+    fn blocks_covers_every_block_touched() {
+        assert_eq!(blocks(1, 4095).collect::<Vec<_>>(), [0]);
+        assert_eq!(blocks(2, 4095).collect::<Vec<_>>(), [0, 1]);
+        assert_eq!(blocks(4096, 8192).collect::<Vec<_>>(), [2]);
+        assert_eq!(blocks(0, 4096).count(), 0);
+        assert_eq!(blocks(0, 4100).count(), 0);
+    }
+
+    #[test]
+    fn forget_drops_every_block_the_range_touches() {
+        let mut table = ChecksumTable::default();
+        for block in 0..4 {
+            table.record(block, block as u32 + 10);
+        }
+        table.forget(0, 4100);
+        assert_eq!(table.iter().count(), 4);
+        table.forget(10, 4090);
+        assert_eq!(table.iter().collect::<Vec<_>>(), [(2, 12), (3, 13)]);
+    }
+
+    #[test]
+    fn table_round_trips_through_a_file() {
+        let path = env::temp_dir().join(format!("checksums-{}.bin", process::id()));
+        let mut table = ChecksumTable::default();
+        table.record(0, 0xE306_9283);
+        table.record(7, 0);
+        table.record(u64::MAX / CHECKSUM_BLOCK, u32::MAX);
+        table.save(&path).unwrap();
+        let loaded = ChecksumTable::load(&path).unwrap();
+        assert_eq!(
+            loaded.iter().collect::<Vec<_>>(),
+            table.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(loaded.get(7), Some(0));
+        assert_eq!(loaded.get(1), None);
+        fs::remove_file(&path).ok();
+    }
+}
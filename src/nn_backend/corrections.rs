@@ -0,0 +1,134 @@
+use std::{collections::BTreeMap, io, path::Path};
+
+use super::table;
+
+// Each entry is stored as a little endian u64 address followed by the byte that lives there
+const ENTRY_LEN: usize = 9;
+
+#[derive(Debug, Default, Clone)]
+pub struct CorrectionTable {
+    // Address -> the byte that was actually written there, only for addresses the network gets wrong
+    entries: BTreeMap<u64, u8>,
+}
+
+impl CorrectionTable {
+    pub fn count(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn get(&self, address: u64) -> Option<u8> {
+        self.entries.get(&address).copied()
+    }
+
+    pub fn patch(&self, buf: &mut [u8], offset: u64) {
+        let end = offset + buf.len() as u64;
+        for (&address, &value) in self.entries.range(offset..end) {
+            buf[(address - offset) as usize] = value;
+        }
+    }
+
+    // Compares what the network predicts for a written range with what was written, returns how many bytes needed correcting
+    pub fn record(&mut self, written: &[u8], predicted: &[u8], offset: u64) -> usize {
+        let mut corrected = 0;
+        for (i, (&value, &prediction)) in written.iter().zip(predicted.iter()).enumerate() {
+            let address = offset + i as u64;
+            if value == prediction {
+                self.entries.remove(&address);
+            } else {
+                self.entries.insert(address, value);
+                corrected += 1;
+            }
+        }
+        corrected
+    }
+
+    pub fn forget(&mut self, len: usize, offset: u64) {
+        let mut forgotten = self.entries.split_off(&offset);
+        let mut after = forgotten.split_off(&(offset + len as u64));
+        self.entries.append(&mut after);
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut entries = BTreeMap::new();
+        table::load::<ENTRY_LEN>(path, |address, value| {
+            entries.insert(address, value[0]);
+        })?;
+        Ok(Self { entries })
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        table::save::<ENTRY_LEN, _>(
+            path,
+            self.entries
+                .iter()
+                .map(|(&address, &value)| (address, [value])),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+
+    // Corrections at 10, 12 and 14
+    fn table() -> CorrectionTable {
+        let mut table = CorrectionTable::default();
+        assert_eq!(table.record(&[1, 2, 3, 4, 5], &[0, 2, 0, 4, 0], 10), 3);
+        table
+    }
+
+    #[test]
+    fn record_keeps_only_what_the_network_got_wrong() {
+        let mut table = table();
+        assert_eq!(table.count(), 3);
+        assert_eq!(table.get(10), Some(1));
+        assert_eq!(table.get(11), None);
+        // Once the network gets it right the correction goes
+        assert_eq!(table.record(&[1, 9], &[1, 2], 10), 1);
+        assert_eq!(table.get(10), None);
+        assert_eq!(table.get(11), Some(9));
+        assert_eq!(table.count(), 3);
+    }
+
+    #[test]
+    fn patch_only_touches_corrected_bytes_in_range() {
+        let table = table();
+        let mut buf = [0xff; 4];
+        table.patch(&mut buf, 11);
+        assert_eq!(buf, [0xff, 3, 0xff, 5]);
+        let mut buf = [0xff; 10];
+        table.patch(&mut buf, 0);
+        assert_eq!(buf, [0xff; 10]);
+    }
+
+    #[test]
+    fn forget_stops_at_both_ends_of_the_range() {
+        let mut table = table();
+        // [12, 14) takes 12 and leaves 14, which is where it ends
+        table.forget(2, 12);
+        assert_eq!(table.get(12), None);
+        assert_eq!(table.get(14), Some(5));
+        // [11, 12) holds nothing, 10 before it and 14 after stay
+        table.forget(1, 11);
+        assert_eq!(table.count(), 2);
+        table.forget(0, 10);
+        assert_eq!(table.get(10), Some(1));
+        table.forget(1, 14);
+        assert_eq!(table.get(14), None);
+        assert_eq!(table.get(10), Some(1));
+    }
+
+    #[test]
+    fn table_round_trips_through_a_file() {
+        let path = env::temp_dir().join(format!("corrections-{}.bin", process::id()));
+        let mut table = table();
+        table.record(&[0xff], &[0], u64::MAX - 1);
+        table.save(&path).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), 4 * ENTRY_LEN as u64);
+        let loaded = CorrectionTable::load(&path).unwrap();
+        assert_eq!(loaded.entries, table.entries);
+        fs::remove_file(&path).ok();
+    }
+}
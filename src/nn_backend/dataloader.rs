@@ -1,4 +1,8 @@
-use std::{fs, path::Path};
+use std::{
+    collections::{BTreeMap, TryReserveError},
+    fs,
+    path::Path,
+};
 
 use burn::{
     data::{
@@ -6,9 +10,11 @@ use burn::{
         dataset::{Dataset, InMemDataset},
     },
     serde,
-    tensor::{backend::Backend, ElementConversion},
+    tensor::backend::Backend,
 };
 
+use super::interface::PREDICT_CHUNK;
+
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct DataItem {
     // Memory Location
@@ -20,29 +26,21 @@ pub struct DataItem {
 }
 
 pub struct CustomDataset {
-    pub overwrite_buf: Option<Vec<u8>>,
-    pub buf_len: Option<usize>,
-    pub overwrite_offset: Option<usize>,
+    // Start -> bytes to train on in place of whatever the dataset holds there
+    pub overwrites: BTreeMap<u64, Vec<u8>>,
     pub dataset: InMemDataset<DataItem>,
     pub max_size: Option<usize>,
 }
 
 impl Dataset<DataItem> for CustomDataset {
     fn get(&self, index: usize) -> Option<DataItem> {
-        if self.overwrite_buf.is_some() {
-            if index < self.overwrite_offset.unwrap()
-                || index >= self.overwrite_offset.unwrap() + self.buf_len.unwrap()
-            {
-                self.dataset.get(index)
-            } else {
-                return Some(DataItem {
-                    address: index as u64,
-                    value: self.overwrite_buf.as_ref().unwrap()
-                        [index - self.overwrite_offset.unwrap()],
-                });
-            }
-        } else {
-            self.dataset.get(index)
+        let address = index as u64;
+        match self.overwrites.range(..=address).next_back() {
+            Some((&start, buf)) if address < start + buf.len() as u64 => Some(DataItem {
+                address,
+                value: buf[(address - start) as usize],
+            }),
+            _ => self.dataset.get(index),
         }
     }
     fn len(&self) -> usize {
@@ -55,24 +53,27 @@ impl CustomDataset {
         c_dataset: CustomDataset,
         device: &B::Device,
         model: crate::model::Model<B>,
-    ) -> Self {
-        let mut dataset: Vec<u8> = Vec::with_capacity(c_dataset.max_size.unwrap_or(0));
-        let batcher = super::batcher::InternalBatcher::<B>::new(device.clone());
-        let batch = batcher.batch(
-            (0..c_dataset.max_size.unwrap_or(0))
-                .collect::<std::vec::Vec<usize>>()
-                .iter()
-                .enumerate()
-                .map(|(i, _v)| DataItem {
-                    address: i as u64,
-                    value: 0u8,
-                })
-                .collect(),
-        );
-        let out: Vec<_> = model.forward(batch.addresses).into_data().value;
-        out.iter()
-            .enumerate()
-            .for_each(|(i, v)| dataset[i] = v.elem::<f32>() as u8);
+        patch: impl FnOnce(&mut [u8]),
+    ) -> Result<Self, TryReserveError> {
+        let max_size = c_dataset.max_size.unwrap_or(0);
+        let mut dataset: Vec<u8> = Vec::new();
+        dataset.try_reserve_exact(max_size)?;
+        dataset.resize(max_size, 0);
+        let batcher = super::batcher::InternalBatcher::<B>::new(device.clone(), model.head());
+        for (chunk, bytes) in dataset.chunks_mut(PREDICT_CHUNK).enumerate() {
+            let start = chunk * PREDICT_CHUNK;
+            let batch = batcher.batch(
+                (start..start + bytes.len())
+                    .map(|address| DataItem {
+                        address: address as u64,
+                        value: 0u8,
+                    })
+                    .collect(),
+            );
+            bytes.copy_from_slice(&model.decode(model.forward(batch.addresses)));
+        }
+        // Lets the caller swap in anything it knows better than the network, so the dataset matches what reads return
+        patch(&mut dataset);
         let dataset = InMemDataset::new(
             dataset
                 .iter()
@@ -83,21 +84,26 @@ impl CustomDataset {
                 })
                 .collect(),
         );
-        CustomDataset {
-            overwrite_buf: c_dataset.overwrite_buf,
-            buf_len: c_dataset.buf_len,
-            overwrite_offset: c_dataset.overwrite_offset,
+        Ok(CustomDataset {
+            overwrites: c_dataset.overwrites,
             dataset,
             max_size: c_dataset.max_size,
+        })
+    }
+    // A dataset of just the given addresses rather than the whole drive
+    pub fn from_items(items: Vec<DataItem>) -> Self {
+        Self {
+            overwrites: BTreeMap::new(),
+            dataset: InMemDataset::new(items),
+            max_size: None,
         }
     }
+    #[allow(dead_code)]
     pub fn new(max_size: usize) -> Self {
         let mut dataset: Vec<u8> = Vec::with_capacity(max_size);
         dataset = dataset.iter().map(|_| 0).collect();
         Self {
-            overwrite_buf: None,
-            buf_len: None,
-            overwrite_offset: None,
+            overwrites: BTreeMap::new(),
             max_size: None,
             dataset: InMemDataset::new(
                 dataset
@@ -128,9 +134,7 @@ impl CustomDataset {
                 .collect(),
         );
         Self {
-            overwrite_buf: None,
-            buf_len: None,
-            overwrite_offset: None,
+            overwrites: BTreeMap::new(),
             dataset,
             max_size: None,
         }
@@ -1,135 +1,1756 @@
 use core::fmt;
-use std::cell::RefCell;
+use std::{
+    any::Any,
+    collections::{BTreeMap, VecDeque},
+    env,
+    error::Error,
+    fs::{self, File},
+    io,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    process,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
 use burn::{
+    config::Config,
     data::{
-        dataloader::{batcher::Batcher, DataLoaderBuilder},
-        dataset::InMemDataset,
+        dataloader::{batcher::Batcher, DataLoader, DataLoaderBuilder},
+        dataset::{Dataset, InMemDataset},
+    },
+    module::{AutodiffModule, Module},
+    optim::{AdamConfig, Optimizer},
+    record::{CompactRecorder, FileRecorder, Recorder},
+    tensor::{
+        backend::{AutodiffBackend, Backend},
+        ElementConversion,
+    },
+    train::{
+        checkpoint::{Checkpointer, FileCheckpointer},
+        logger::{FileMetricLogger, MetricLogger},
+        metric::{Adaptor, LossInput, LossMetric, Metric, MetricMetadata, Numeric},
+        renderer::{MetricState, MetricsRenderer, TrainingProgress},
+        ClassificationOutput, RegressionOutput, TrainStep, ValidStep,
     },
-    optim::AdamConfig,
-    record::CompactRecorder,
-    tensor::{backend::AutodiffBackend, ElementConversion},
-    train::{metric::LossMetric, LearnerBuilder},
 };
 
 use crate::{
-    batcher,
+    batcher::{self, Batch},
+    checksums::{self, crc32c, ChecksumTable, ReadCheck, CHECKSUM_BLOCK},
+    corrections::CorrectionTable,
     dataloader::{self, CustomDataset, DataItem},
-    model::ModelConfig,
-    trainer::TrainingConfig,
+    journal::{Journal, Record},
+    model::{LegacyModel, LegacyModelRecord, Model, ModelConfig, ModelRecord, OutputHead},
+    overlay::Overlay,
+    ranges::{Extent, RangeMap},
+    renderer::RenderMode,
+    replay,
+    trainer::{
+        ReplaySampling, TrainingConfig, DEFAULT_CHECKPOINTS, DEFAULT_CHECK_EVERY, MAX_REPLAY_RATIO,
+    },
 };
 
-#[derive(Debug, Clone)]
-pub struct NNError;
+const MODEL_FILE: &str = "model";
+const CONFIG_FILE: &str = "config.json";
+const CORRECTIONS_FILE: &str = "corrections.bin";
+const WRITTEN_FILE: &str = "written.bin";
+// The drive's size as a little endian u64, so every saved drive keeps its own
+const SIZE_FILE: &str = "size.bin";
+const CHECKSUMS_FILE: &str = "checksums.bin";
+pub const JOURNAL_FILE: &str = "journal.bin";
+// Cached predictions are kept in blocks of this many bytes
+const CACHE_BLOCK: u64 = 4096;
+// Most addresses put through the network in one go outside of training
+pub const PREDICT_CHUNK: usize = 65536;
+
+type BoxedError = Box<dyn Error + Send + Sync>;
+
+#[derive(Debug)]
+pub enum NNError {
+    // The request reaches past the end of the drive
+    OutOfRange {
+        offset: u64,
+        len: usize,
+        max_size: usize,
+    },
+    // Training finished with a loss that isn't a number, the old weights are kept
+    TrainingDiverged {
+        loss: f32,
+    },
+    // Training finished but the written range doesn't read back
+    VerifyMismatch {
+        verified: usize,
+        expected: usize,
+    },
+    ModelLoad {
+        path: PathBuf,
+        source: BoxedError,
+    },
+    ModelSave {
+        path: PathBuf,
+        source: BoxedError,
+    },
+    // A write or trim couldn't be made durable, so it isn't acknowledged
+    Journal {
+        path: PathBuf,
+        source: io::Error,
+    },
+    // There isn't enough memory to build the retraining set for the whole drive
+    CapacityExceeded {
+        max_size: usize,
+    },
+    // A snapshot couldn't be made, found or restored
+    Snapshot {
+        name: String,
+        source: io::Error,
+    },
+    // A new drive couldn't be started off its parent
+    Fork {
+        path: PathBuf,
+        source: io::Error,
+    },
+    // A block read back differently from when it was written
+    ChecksumMismatch {
+        offset: u64,
+        len: usize,
+    },
+    // The backend panicked part way through, carries the panic message
+    Backend(String),
+}
+
+impl NNError {
+    pub fn errno(&self) -> i32 {
+        match self {
+            NNError::OutOfRange { .. } => libc::EINVAL,
+            NNError::CapacityExceeded { .. } => libc::ENOSPC,
+            NNError::Snapshot { source, .. } => match source.kind() {
+                io::ErrorKind::InvalidInput => libc::EINVAL,
+                io::ErrorKind::AlreadyExists => libc::EEXIST,
+                _ => io_errno(source),
+            },
+            // A full disk or a permission problem should reach the client as itself, not as EIO
+            NNError::ModelLoad { source, .. } | NNError::ModelSave { source, .. } => {
+                io_errno(source.as_ref())
+            }
+            NNError::Journal { source, .. } | NNError::Fork { source, .. } => io_errno(source),
+            NNError::TrainingDiverged { .. }
+            | NNError::VerifyMismatch { .. }
+            | NNError::ChecksumMismatch { .. }
+            | NNError::Backend(_) => libc::EIO,
+        }
+    }
+
+    fn load(path: &Path, source: impl Into<BoxedError>) -> Self {
+        NNError::ModelLoad {
+            path: path.to_path_buf(),
+            source: source.into(),
+        }
+    }
+
+    fn save(path: &Path, source: impl Into<BoxedError>) -> Self {
+        NNError::ModelSave {
+            path: path.to_path_buf(),
+            source: source.into(),
+        }
+    }
+
+    pub fn snapshot(name: &str, source: io::Error) -> Self {
+        NNError::Snapshot {
+            name: name.to_string(),
+            source,
+        }
+    }
+
+    pub fn fork(path: &Path, source: io::Error) -> Self {
+        NNError::Fork {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+
+    fn journal(path: &Path, source: io::Error) -> Self {
+        NNError::Journal {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+}
+
+// The errno of the first OS error down the source chain, EIO when nothing in it came from the OS
+fn io_errno(source: &(dyn Error + 'static)) -> i32 {
+    let mut cause = Some(source);
+    while let Some(error) = cause {
+        if let Some(errno) = error
+            .downcast_ref::<io::Error>()
+            .and_then(io::Error::raw_os_error)
+        {
+            return errno;
+        }
+        cause = error.source();
+    }
+    libc::EIO
+}
 
 impl fmt::Display for NNError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Something went wrong with training the NN") // TODO: This error should be a lot better
+        match self {
+            NNError::OutOfRange {
+                offset,
+                len,
+                max_size,
+            } => write!(
+                f,
+                "{} bytes at offset {} is past the end of the {} byte drive",
+                len, offset, max_size
+            ),
+            NNError::TrainingDiverged { loss } => {
+                write!(f, "Training diverged with a loss of {}", loss)
+            }
+            NNError::VerifyMismatch { verified, expected } => write!(
+                f,
+                "Only {} of {} written bytes read back correctly",
+                verified, expected
+            ),
+            NNError::ModelLoad { path, .. } => {
+                write!(f, "Failed to load the model from {}", path.display())
+            }
+            NNError::ModelSave { path, .. } => {
+                write!(f, "Failed to save the model to {}", path.display())
+            }
+            NNError::Journal { path, .. } => {
+                write!(f, "Failed to write the journal at {}", path.display())
+            }
+            NNError::CapacityExceeded { max_size } => {
+                write!(f, "Not enough memory to retrain a {} byte drive", max_size)
+            }
+            NNError::Snapshot { name, .. } => write!(f, "Snapshot {:?} failed", name),
+            NNError::Fork { path, .. } => {
+                write!(f, "Failed to clone the drive into {}", path.display())
+            }
+            NNError::ChecksumMismatch { offset, len } => write!(
+                f,
+                "The {} byte block at offset {} doesn't match its checksum",
+                len, offset
+            ),
+            NNError::Backend(message) => write!(f, "The backend failed: {}", message),
+        }
+    }
+}
+
+impl Error for NNError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            NNError::ModelLoad { source, .. } | NNError::ModelSave { source, .. } => {
+                Some(source.as_ref())
+            }
+            NNError::Journal { source, .. }
+            | NNError::Snapshot { source, .. }
+            | NNError::Fork { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    // Every pending byte read back exactly
+    Exact,
+    // The train loss dropped below the configured threshold
+    LossThreshold,
+    // Ran for as many epochs as a run is allowed
+    MaxEpochs,
+    // Ran out of wall time
+    TimeLimit,
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StopReason::Exact => write!(f, "exact"),
+            StopReason::LossThreshold => write!(f, "loss_threshold"),
+            StopReason::MaxEpochs => write!(f, "max_epochs"),
+            StopReason::TimeLimit => write!(f, "time_limit"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TrainReport {
+    pub epochs: usize,
+    // Which stopping condition ended the run
+    pub stopped: StopReason,
+    // How many bytes of pending writes went into the run
+    pub trained_bytes: usize,
+    pub train_loss: f32,
+    pub valid_loss: f32,
+    // How many bytes of the written range read back exactly once training finished
+    pub verified_bytes: usize,
+    // How many bytes of the written range went into the correction table instead of the weights
+    pub corrected_bytes: usize,
+    // How many bytes written before this run stopped reading back from the new weights and went into the correction table
+    pub drifted_bytes: usize,
+    // How sure the new weights are of the least certain byte they were trained on, a byte near 0 is one nudge away from flipping
+    pub min_confidence: f32,
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for TrainReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "epochs={} stopped={} trained_bytes={} train_loss={} valid_loss={} verified_bytes={} corrected_bytes={} drifted_bytes={} min_confidence={} elapsed={:?}",
+            self.epochs,
+            self.stopped,
+            self.trained_bytes,
+            self.train_loss,
+            self.valid_loss,
+            self.verified_bytes,
+            self.corrected_bytes,
+            self.drifted_bytes,
+            self.min_confidence,
+            self.elapsed
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ScrubReport {
+    // Blocks whose checksum was compared
+    pub checked: usize,
+    // Blocks that no longer matched their checksum
+    pub drifted: usize,
+    // Drifted blocks that were recovered and read back right again
+    pub repaired: usize,
+    // Drifted blocks nothing could be recovered for
+    pub lost: usize,
+}
+
+impl ScrubReport {
+    fn add(&mut self, other: &ScrubReport) {
+        self.checked += other.checked;
+        self.drifted += other.drifted;
+        self.repaired += other.repaired;
+        self.lost += other.lost;
+    }
+}
+
+impl fmt::Display for ScrubReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "checked={} drifted={} repaired={} lost={}",
+            self.checked, self.drifted, self.repaired, self.lost
+        )
     }
 }
 
 pub struct TheNetwork<A: AutodiffBackend> {
     // This will be the actual network along with all the associated functions for handling training the new network and getting info from it (infering / reading)
-    model: RefCell<super::model::Model<A>>,
+    // Only locked long enough to clone or swap the model, so reads run inference in parallel with each other and with training
+    model: Mutex<super::model::Model<A>>,
+    // Held for a whole training run so only one write trains at a time
+    training: Mutex<()>,
+    // Bytes the weights got wrong, None unless the drive runs in hybrid mode
+    corrections: Option<Mutex<CorrectionTable>>,
+    // Every range that has been written, anything outside it reads as zero
+    written: Mutex<RangeMap>,
+    // Writes that haven't been trained in yet, reads see them straight away
+    pending: Mutex<Overlay>,
+    // Writes the current training run is absorbing, still served from here until the new weights are swapped in
+    in_flight: Mutex<Overlay>,
+    // Every write and trim since the last save goes here before it's acknowledged, None for drives that are never saved
+    journal: Option<Mutex<Journal>>,
+    // Once this many bytes are pending a write trains them in itself instead of waiting for a flush
+    writeback_threshold: usize,
+    // How training runs report their progress
+    render_mode: RenderMode,
+    // Ranges committed by recent training runs, oldest first, for recency weighted replay
+    history: Mutex<VecDeque<(u64, usize)>>,
+    // Checksum of every written block as it was when last written, what the scrubber checks against
+    checksums: Mutex<ChecksumTable>,
+    // What reads do about blocks that don't match their checksum
+    read_check: ReadCheck,
+    // Weights from the last scrub that found nothing drifted, drifted blocks are recovered from these when they still match
+    clean: Mutex<Model<A>>,
+    // Running totals over every scrub since the drive was opened
+    scrub_totals: Mutex<ScrubReport>,
+    // Predictions memoized by cache requests, keyed by block number and thrown away whenever the weights change
+    cached: Mutex<BTreeMap<u64, Vec<u8>>>,
     training_config: TrainingConfig,
     device: A::Device,
     max_size: usize,
 }
 
 impl<A: AutodiffBackend> TheNetwork<A> {
-    pub fn init() -> Self {
+    pub fn init(max_size: usize, model_config: ModelConfig) -> Self {
         let device = A::Device::default();
-        let model_config = ModelConfig::new(64, 1);
         let model = model_config.init::<A>(&device);
         let training_config = TrainingConfig::new(model_config, AdamConfig::new());
         Self {
-            model: RefCell::new(model),
+            clean: Mutex::new(model.clone()),
+            model: Mutex::new(model),
+            training: Mutex::new(()),
+            corrections: None,
+            written: Mutex::new(RangeMap::default()),
+            pending: Mutex::new(Overlay::default()),
+            in_flight: Mutex::new(Overlay::default()),
+            journal: None,
+            writeback_threshold: 0,
+            render_mode: RenderMode::Quiet,
+            history: Mutex::new(VecDeque::new()),
+            checksums: Mutex::new(ChecksumTable::default()),
+            read_check: ReadCheck::Off,
+            scrub_totals: Mutex::new(ScrubReport::default()),
+            cached: Mutex::new(BTreeMap::new()),
             training_config,
             device,
-            max_size: 0,
+            max_size,
         }
     }
 
-    pub fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<(), NNError> {
-        // let's calculate the bits we need to get: offset * 8
-        let batcher = super::batcher::InternalBatcher::<A>::new(self.device.clone());
-        let batch = batcher.batch(
-            buf.iter()
-                .enumerate()
-                .map(|(i, &_v)| super::dataloader::DataItem {
-                    address: i as u64 + offset,
-                    value: 0u8,
-                })
-                .collect(),
-        );
-        let out: Vec<_> = self
-            .model
-            .borrow()
-            .forward(batch.addresses)
-            .into_data()
-            .value;
-        out.iter()
-            .enumerate()
-            .for_each(|(i, v)| buf[i] = v.elem::<f32>() as u8);
+    pub fn with_corrections(mut self) -> Self {
+        if self.corrections.is_none() {
+            self.corrections = Some(Mutex::new(CorrectionTable::default()));
+        }
+        self
+    }
+
+    // Picks up whatever an earlier run journaled but never saved, it comes back as pending writes
+    pub fn with_journal(mut self, model_dir: &Path) -> Result<Self, NNError> {
+        let path = model_dir.join(JOURNAL_FILE);
+        let (journal, records) = Journal::open(&path).map_err(|e| NNError::load(&path, e))?;
+        for record in records {
+            match record {
+                Record::Write { offset, bytes } => {
+                    self.check_range(bytes.len(), offset)?;
+                    self.pending.lock().unwrap().insert(&bytes, offset);
+                }
+                Record::Trim { offset, len } => self.forget(len, offset)?,
+            }
+        }
+        self.journal = Some(Mutex::new(journal));
+        Ok(self)
+    }
+
+    // A drive that isn't journaled still replays a journal an earlier run left, and keeps it up until a save empties it
+    pub fn with_leftover_journal(self, model_dir: &Path) -> Result<Self, NNError> {
+        if !model_dir.join(JOURNAL_FILE).exists() {
+            return Ok(self);
+        }
+        let mut network = self.with_journal(model_dir)?;
+        if let Some(journal) = &mut network.journal {
+            journal.get_mut().unwrap().retire_when_empty();
+        }
+        Ok(network)
+    }
+
+    pub fn with_writeback_threshold(mut self, writeback_threshold: usize) -> Self {
+        self.writeback_threshold = writeback_threshold;
+        self
+    }
+
+    pub fn with_read_check(mut self, read_check: ReadCheck) -> Self {
+        self.read_check = read_check;
+        self
+    }
+
+    pub fn with_render_mode(mut self, render_mode: RenderMode) -> Self {
+        self.render_mode = render_mode;
+        self
+    }
+
+    pub fn with_artifact_dir(mut self, artifact_dir: &Path) -> Self {
+        self.training_config.artifact_dir = Some(artifact_dir.to_string_lossy().into_owned());
+        self
+    }
+
+    pub fn with_max_epochs(mut self, num_epochs: usize) -> Self {
+        self.training_config.num_epochs = num_epochs;
+        self
+    }
+
+    pub fn with_loss_threshold(mut self, loss_threshold: f32) -> Self {
+        self.training_config.loss_threshold = Some(loss_threshold);
+        self
+    }
+
+    pub fn with_time_limit(mut self, time_limit: u64) -> Self {
+        self.training_config.time_limit = Some(time_limit);
+        self
+    }
+
+    pub fn with_replay_ratio(mut self, replay_ratio: f64) -> Self {
+        self.training_config.replay_ratio = Some(replay_ratio);
+        self
+    }
+
+    pub fn with_replay_sampling(mut self, replay_sampling: ReplaySampling) -> Self {
+        self.training_config.replay_sampling = Some(replay_sampling);
+        self
+    }
+
+    pub fn with_checkpoints(mut self, checkpoints: usize) -> Self {
+        self.training_config.checkpoints = Some(checkpoints);
+        self
+    }
+
+    // Drives that were never given one share a scratch directory for the life of the process
+    fn artifact_dir(&self) -> PathBuf {
+        match &self.training_config.artifact_dir {
+            Some(artifact_dir) => PathBuf::from(artifact_dir),
+            None => env::temp_dir().join(format!("functional-drive-{}", process::id())),
+        }
+    }
+
+    pub fn correction_count(&self) -> usize {
+        self.corrections
+            .as_ref()
+            .map_or(0, |corrections| corrections.lock().unwrap().count())
+    }
+
+    // The model config only shapes brand new drives, saved ones keep the architecture they were trained with
+    pub fn open(
+        model_dir: &Path,
+        max_size: usize,
+        model_config: ModelConfig,
+    ) -> Result<Self, NNError> {
+        // A directory without a saved config is a brand new drive
+        if model_dir.join(CONFIG_FILE).exists() {
+            Self::load(model_dir, max_size)
+        } else {
+            Ok(Self::init(max_size, model_config))
+        }
+    }
+
+    pub fn load(model_dir: &Path, max_size: usize) -> Result<Self, NNError> {
+        let device = A::Device::default();
+        let max_size = load_size(model_dir, max_size)?;
+        let (training_config, model) = load_weights::<A>(model_dir, &device)?;
+        let (corrections, written) = load_side_tables(model_dir, max_size)?;
+        let corrections = corrections.map(Mutex::new);
+        let checksums = load_checksums(model_dir)?;
+        Ok(Self {
+            clean: Mutex::new(model.clone()),
+            model: Mutex::new(model),
+            training: Mutex::new(()),
+            corrections,
+            written: Mutex::new(written),
+            pending: Mutex::new(Overlay::default()),
+            in_flight: Mutex::new(Overlay::default()),
+            journal: None,
+            writeback_threshold: 0,
+            render_mode: RenderMode::Quiet,
+            history: Mutex::new(VecDeque::new()),
+            checksums: Mutex::new(checksums),
+            read_check: ReadCheck::Off,
+            scrub_totals: Mutex::new(ScrubReport::default()),
+            cached: Mutex::new(BTreeMap::new()),
+            training_config,
+            device,
+            max_size,
+        })
+    }
+
+    pub fn save(&self, model_dir: &Path) -> Result<(), NNError> {
+        // No training run can swap the weights mid-save, or the journal could be cut back past writes the saved weights don't hold
+        let _training = self.training.lock().unwrap();
+        let mut journal = self.journal.as_ref().map(|journal| journal.lock().unwrap());
+        // Everything is written next to the real files first and renamed over them, so a crash mid-save leaves the last good copy
+        fs::create_dir_all(model_dir).map_err(|e| NNError::save(model_dir, e))?;
+        let extension = <CompactRecorder as FileRecorder<A>>::file_extension();
+        let staged_model = model_dir.join(format!("{MODEL_FILE}-staged"));
+        let staged_config = model_dir.join(format!("{CONFIG_FILE}-staged"));
+        self.training_config
+            .save(&staged_config)
+            .map_err(|e| NNError::save(model_dir, e))?;
+        self.model()
+            .save_file(staged_model.clone(), &CompactRecorder::new())
+            .map_err(|e| NNError::save(model_dir, e))?;
+        if let Some(corrections) = &self.corrections {
+            let corrections = corrections.lock().unwrap();
+            save_side_table(model_dir, CORRECTIONS_FILE, |path| corrections.save(path))?;
+        }
+        let written = self.written.lock().unwrap();
+        save_side_table(model_dir, WRITTEN_FILE, |path| written.save(path))?;
+        let checksums = self.checksums.lock().unwrap();
+        save_side_table(model_dir, CHECKSUMS_FILE, |path| checksums.save(path))?;
+        save_side_table(model_dir, SIZE_FILE, |path| {
+            fs::write(path, (self.max_size as u64).to_le_bytes())
+        })?;
+        commit_file(
+            &staged_model.with_extension(extension),
+            &model_dir.join(MODEL_FILE).with_extension(extension),
+        )
+        .map_err(|e| NNError::save(model_dir, e))?;
+        // Last, since its being there is what marks the directory as a saved drive
+        commit_file(&staged_config, &model_dir.join(CONFIG_FILE))
+            .map_err(|e| NNError::save(model_dir, e))?;
+        File::open(model_dir)
+            .and_then(|dir| dir.sync_all())
+            .map_err(|e| NNError::save(model_dir, e))?;
+        // The saved weights hold everything journaled except what's still pending
+        if let Some(journal) = &mut journal {
+            let pending = self.pending.lock().unwrap();
+            journal
+                .compact(&pending)
+                .map_err(|e| NNError::journal(journal.path(), e))?;
+        }
         Ok(())
     }
 
-    pub fn train(&self, buf: &[u8], offset: u64) -> Result<(), NNError> {
-        A::seed(self.training_config.seed);
-        let batcher_train = batcher::InternalBatcher::<A>::new(self.device.clone());
-        let batcher_valid = batcher::InternalBatcher::<A::InnerBackend>::new(self.device.clone()); // TODO: Got to work out this line here not sure what I can really do about it though
-        let training_dataset = dataloader::CustomDataset::retrain(
-            CustomDataset {
-                overwrite_buf: Some(buf.to_vec()),
-                buf_len: Some(buf.len()),
-                overwrite_offset: Some(offset as usize),
-                dataset: InMemDataset::new(vec![DataItem {
-                    address: 0,
-                    value: 0,
-                }]),
-                max_size: Some(self.max_size),
-            },
-            &self.device,
-            self.model.borrow().clone(),
-        );
-        let testing_dataset = dataloader::CustomDataset::retrain(
-            CustomDataset {
-                overwrite_buf: Some(buf.to_vec()),
-                buf_len: Some(buf.len()),
-                overwrite_offset: Some(offset as usize),
-                dataset: InMemDataset::new(vec![DataItem {
-                    address: 0,
-                    value: 0,
-                }]),
-                max_size: Some(self.max_size),
+    fn model(&self) -> super::model::Model<A> {
+        self.model.lock().unwrap().clone()
+    }
+
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    pub fn check_range(&self, len: usize, offset: u64) -> Result<(), NNError> {
+        check_range(len, offset, self.max_size)
+    }
+
+    pub fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<(), NNError> {
+        self.check_range(buf.len(), offset)?;
+        if matches!(self.read_check, ReadCheck::Off) || buf.is_empty() {
+            self.read_unchecked(buf, offset);
+            return Ok(());
+        }
+        // Checksums cover whole blocks, so the read is widened to them and cut back down afterwards
+        let (start, end) = block_span(buf.len(), offset, self.max_size);
+        let mut blocks = vec![0u8; (end - start) as usize];
+        self.read_unchecked(&mut blocks, start);
+        // Blocks with writes still waiting to be trained in haven't got a checksum for what they hold yet
+        verify_blocks(
+            &self.checksums.lock().unwrap(),
+            &blocks,
+            start,
+            self.read_check,
+            |len, offset| {
+                // One lock at a time, a failed commit takes in_flight and pending together
+                let pending = self.pending.lock().unwrap().overlaps(len, offset);
+                pending || self.in_flight.lock().unwrap().overlaps(len, offset)
             },
+        )?;
+        let from = (offset - start) as usize;
+        buf.copy_from_slice(&blocks[from..from + buf.len()]);
+        Ok(())
+    }
+
+    fn read_unchecked(&self, buf: &mut [u8], offset: u64) {
+        if !self.read_cached(buf, offset) {
+            self.predict(&self.model(), buf, offset);
+        }
+        if let Some(corrections) = &self.corrections {
+            corrections.lock().unwrap().patch(buf, offset);
+        }
+        self.written.lock().unwrap().zero_unwritten(buf, offset);
+        self.in_flight.lock().unwrap().patch(buf, offset);
+        self.pending.lock().unwrap().patch(buf, offset);
+    }
+
+    pub fn extents(&self, len: usize, offset: u64) -> Result<Vec<Extent>, NNError> {
+        self.check_range(len, offset)?;
+        // Writes that haven't been trained in yet are still allocated, reads return them
+        let mut written = self.written.lock().unwrap().clone();
+        for overlay in [&self.in_flight, &self.pending] {
+            for (&start, bytes) in overlay.lock().unwrap().ranges() {
+                written.insert(bytes.len(), start);
+            }
+        }
+        Ok(written.extents(len, offset))
+    }
+
+    // How sure the current weights are of each byte, whatever the correction table or pending writes say about it
+    pub fn confidence(&self, len: usize, offset: u64) -> Result<Vec<f32>, NNError> {
+        self.check_range(len, offset)?;
+        let addresses: Vec<u64> = (offset..offset + len as u64).collect();
+        Ok(predict_confidence(
+            &self.model().valid(),
             &self.device,
-            self.model.borrow().clone(),
-        );
+            &addresses,
+        ))
+    }
+
+    pub fn written_bytes(&self) -> u64 {
+        self.written.lock().unwrap().written_bytes()
+    }
+
+    pub fn dirty_bytes(&self) -> usize {
+        self.pending.lock().unwrap().dirty_bytes()
+    }
+
+    pub fn cache(&self, len: usize, offset: u64) -> Result<(), NNError> {
+        self.check_range(len, offset)?;
+        if len == 0 {
+            return Ok(());
+        }
+        // Held while predicting so a training run can't swap the weights underneath us and leave stale blocks behind
+        let mut cached = self.cached.lock().unwrap();
+        let model = self.model();
+        let first = offset / CACHE_BLOCK;
+        let last = (offset + len as u64 - 1) / CACHE_BLOCK;
+        for block in first..=last {
+            if cached.contains_key(&block) {
+                continue;
+            }
+            let block_start = block * CACHE_BLOCK;
+            let block_len = CACHE_BLOCK.min(self.max_size as u64 - block_start) as usize;
+            let mut bytes = vec![0u8; block_len];
+            self.predict(&model, &mut bytes, block_start);
+            cached.insert(block, bytes);
+        }
+        Ok(())
+    }
+
+    fn read_cached(&self, buf: &mut [u8], offset: u64) -> bool {
+        if buf.is_empty() {
+            return false;
+        }
+        let cached = self.cached.lock().unwrap();
+        let end = offset + buf.len() as u64;
+        let first = offset / CACHE_BLOCK;
+        let last = (end - 1) / CACHE_BLOCK;
+        if !(first..=last).all(|block| cached.contains_key(&block)) {
+            return false;
+        }
+        for block in first..=last {
+            let bytes = &cached[&block];
+            let block_start = block * CACHE_BLOCK;
+            let from = offset.max(block_start);
+            let to = end.min(block_start + bytes.len() as u64);
+            buf[(from - offset) as usize..(to - offset) as usize].copy_from_slice(
+                &bytes[(from - block_start) as usize..(to - block_start) as usize],
+            );
+        }
+        true
+    }
+
+    fn swap_model(&self, model: Model<A>) {
+        let mut cached = self.cached.lock().unwrap();
+        *self.model.lock().unwrap() = model;
+        cached.clear();
+    }
+
+    fn predict(&self, model: &Model<A>, buf: &mut [u8], offset: u64) {
+        predict(&model.valid(), &self.device, buf, offset);
+    }
+
+    pub fn forget(&self, len: usize, offset: u64) -> Result<(), NNError> {
+        // Once it's out of the written ranges the range reads as zero, and the next training run pulls the weights toward that too
+        self.check_range(len, offset)?;
+        // A training run puts its whole batch back into the written ranges, corrections and checksums when it finishes, so a trim waits for it rather than being undone by it
+        let _training = self.training.lock().unwrap();
+        let mut journal = self.journal.as_ref().map(|journal| journal.lock().unwrap());
+        if let Some(journal) = &mut journal {
+            journal
+                .append_trim(len, offset)
+                .map_err(|e| NNError::journal(journal.path(), e))?;
+        }
+        self.pending.lock().unwrap().remove(len, offset);
+        if let Some(corrections) = &self.corrections {
+            corrections.lock().unwrap().forget(len, offset);
+        }
+        self.checksums.lock().unwrap().forget(len, offset);
+        self.written.lock().unwrap().remove(len, offset);
+        Ok(())
+    }
+
+    pub fn write(&self, buf: &[u8], offset: u64) -> Result<Option<TrainReport>, NNError> {
+        self.check_range(buf.len(), offset)?;
+        let dirty_bytes = {
+            // Held until the write is pending too, so a save never cuts the journal back past it
+            let mut journal = self.journal.as_ref().map(|journal| journal.lock().unwrap());
+            if let Some(journal) = &mut journal {
+                journal
+                    .append_write(buf, offset)
+                    .map_err(|e| NNError::journal(journal.path(), e))?;
+            }
+            let mut pending = self.pending.lock().unwrap();
+            pending.insert(buf, offset);
+            pending.dirty_bytes()
+        };
+        if dirty_bytes >= self.writeback_threshold {
+            self.commit()
+        } else {
+            Ok(None)
+        }
+    }
+
+    // Trains every pending write into the weights in one run, None if there was nothing to do
+    pub fn commit(&self) -> Result<Option<TrainReport>, NNError> {
+        let _training = self.training.lock().unwrap();
+        let batch = {
+            let mut pending = self.pending.lock().unwrap();
+            if pending.is_empty() {
+                return Ok(None);
+            }
+            let batch = std::mem::take(&mut *pending);
+            *self.in_flight.lock().unwrap() = batch.clone();
+            batch
+        };
+        // Taken before training while reads still return what every block should hold, the batch included
+        let checksums = self.block_checksums(&batch);
+        let report = self.train(&batch);
+        if report.is_ok() {
+            let mut table = self.checksums.lock().unwrap();
+            for (block, checksum) in checksums {
+                table.record(block, checksum);
+            }
+        }
+        // Pending before in_flight, the same order the batch was taken in
+        let mut pending = self.pending.lock().unwrap();
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if report.is_err() {
+            // Nothing made it into the weights so put the writes back, under anything written since
+            let mut restored = std::mem::take(&mut *in_flight);
+            restored.extend(&pending);
+            *pending = restored;
+        }
+        *in_flight = Overlay::default();
+        report.map(Some)
+    }
+
+    fn block_checksums(&self, batch: &Overlay) -> Vec<(u64, u32)> {
+        let mut blocks: Vec<u64> = batch
+            .ranges()
+            .iter()
+            .flat_map(|(&offset, buf)| checksums::blocks(buf.len(), offset))
+            .collect();
+        blocks.dedup();
+        blocks
+            .into_iter()
+            .map(|block| {
+                let (offset, len) = self.block_range(block);
+                let mut bytes = vec![0u8; len];
+                self.read_unchecked(&mut bytes, offset);
+                (block, crc32c(&bytes))
+            })
+            .collect()
+    }
+
+    fn block_range(&self, block: u64) -> (u64, usize) {
+        let offset = block * CHECKSUM_BLOCK;
+        (
+            offset,
+            CHECKSUM_BLOCK.min(self.max_size as u64 - offset) as usize,
+        )
+    }
+
+    // What a set of weights says is stored, without anything still waiting to be trained in
+    fn read_stored(&self, model: &Model<A>, buf: &mut [u8], offset: u64) {
+        self.predict(model, buf, offset);
+        if let Some(corrections) = &self.corrections {
+            corrections.lock().unwrap().patch(buf, offset);
+        }
+        self.written.lock().unwrap().zero_unwritten(buf, offset);
+    }
+
+    // Re-reads every checksummed block and repairs the ones training elsewhere has knocked out of shape
+    pub fn scrub(&self) -> Result<ScrubReport, NNError> {
+        let _training = self.training.lock().unwrap();
+        let model = self.model();
+        let clean = self.clean.lock().unwrap().clone();
+        let blocks: Vec<(u64, u32)> = self.checksums.lock().unwrap().iter().collect();
+        let mut report = ScrubReport::default();
+        let mut repairs = Overlay::default();
+        for (block, checksum) in blocks {
+            let (offset, len) = self.block_range(block);
+            // Pending writes are about to change the block anyway
+            let dirty = self.pending.lock().unwrap().overlaps(len, offset);
+            if dirty {
+                continue;
+            }
+            report.checked += 1;
+            let mut bytes = vec![0u8; len];
+            self.read_stored(&model, &mut bytes, offset);
+            if crc32c(&bytes) == checksum {
+                continue;
+            }
+            report.drifted += 1;
+            self.read_stored(&clean, &mut bytes, offset);
+            if crc32c(&bytes) == checksum {
+                repairs.insert(&bytes, offset);
+            } else {
+                report.lost += 1;
+            }
+        }
+        if !repairs.is_empty() {
+            report.repaired = self.repair(&repairs)?;
+            report.lost += report.drifted - report.lost - report.repaired;
+        }
+        if report.drifted == 0 {
+            *self.clean.lock().unwrap() = model;
+        }
+        self.scrub_totals.lock().unwrap().add(&report);
+        Ok(report)
+    }
+
+    // Puts recovered blocks back, returns how many read back right afterwards
+    fn repair(&self, repairs: &Overlay) -> Result<usize, NNError> {
+        match &self.corrections {
+            // The table can hold the recovered bytes outright
+            Some(corrections) => {
+                let model = self.model();
+                let mut corrections = corrections.lock().unwrap();
+                for (&offset, bytes) in repairs.ranges().iter() {
+                    let mut predicted = vec![0u8; bytes.len()];
+                    self.predict(&model, &mut predicted, offset);
+                    corrections.record(bytes, &predicted, offset);
+                }
+                Ok(repairs
+                    .ranges()
+                    .values()
+                    .map(|bytes| bytes.len().div_ceil(CHECKSUM_BLOCK as usize))
+                    .sum())
+            }
+            None => {
+                self.train(repairs)?;
+                let model = self.model();
+                let checksums = self.checksums.lock().unwrap().clone();
+                Ok(repairs
+                    .ranges()
+                    .iter()
+                    .flat_map(|(&offset, bytes)| checksums::blocks(bytes.len(), offset))
+                    .filter(|&block| {
+                        let (offset, len) = self.block_range(block);
+                        let mut bytes = vec![0u8; len];
+                        self.read_stored(&model, &mut bytes, offset);
+                        checksums.get(block) == Some(crc32c(&bytes))
+                    })
+                    .count())
+            }
+        }
+    }
+
+    pub fn scrub_totals(&self) -> ScrubReport {
+        self.scrub_totals.lock().unwrap().clone()
+    }
+
+    fn train(&self, batch: &Overlay) -> Result<TrainReport, NNError> {
+        let started = Instant::now();
+        let model = self.model();
+        let corrections = self
+            .corrections
+            .as_ref()
+            .map(|corrections| corrections.lock().unwrap().clone());
+        let written = self.written.lock().unwrap().clone();
+        // Everything outside the batch is retrained toward what reads return for it today
+        let known = |dataset: &mut [u8]| {
+            if let Some(corrections) = &corrections {
+                corrections.patch(dataset, 0);
+            }
+            written.zero_unwritten(dataset, 0);
+        };
+        A::seed(self.training_config.seed);
+        let head = self.training_config.model.head();
+        let batcher_train = batcher::InternalBatcher::<A>::new(self.device.clone(), head);
+        let batcher_valid =
+            batcher::InternalBatcher::<A::InnerBackend>::new(self.device.clone(), head); // TODO: Got to work out this line here not sure what I can really do about it though
+        let (training_dataset, testing_dataset) = match self.training_config.replay_ratio {
+            Some(replay_ratio) => {
+                let dataset = Arc::new(self.replay_dataset(
+                    batch,
+                    &model,
+                    replay_ratio,
+                    &written,
+                    corrections.as_ref(),
+                ));
+                (dataset.clone(), dataset)
+            }
+            None => {
+                let training_dataset = Arc::new(
+                    dataloader::CustomDataset::retrain(
+                        CustomDataset {
+                            overwrites: batch.ranges().clone(),
+                            dataset: InMemDataset::new(vec![DataItem {
+                                address: 0,
+                                value: 0,
+                            }]),
+                            max_size: Some(self.max_size),
+                        },
+                        &self.device,
+                        model.valid(),
+                        known,
+                    )
+                    .map_err(|_| NNError::CapacityExceeded {
+                        max_size: self.max_size,
+                    })?,
+                );
+                let testing_dataset = Arc::new(
+                    dataloader::CustomDataset::retrain(
+                        CustomDataset {
+                            overwrites: batch.ranges().clone(),
+                            dataset: InMemDataset::new(vec![DataItem {
+                                address: 0,
+                                value: 0,
+                            }]),
+                            max_size: Some(self.max_size),
+                        },
+                        &self.device,
+                        model.valid(),
+                        known,
+                    )
+                    .map_err(|_| NNError::CapacityExceeded {
+                        max_size: self.max_size,
+                    })?,
+                );
+                (training_dataset, testing_dataset)
+            }
+        };
         let dataloader_train = DataLoaderBuilder::new(batcher_train)
             .batch_size(self.training_config.batch_size)
             .shuffle(self.training_config.seed)
             .num_workers(self.training_config.num_workers)
-            .build(training_dataset);
+            .build(training_dataset.clone());
         let dataloader_test = DataLoaderBuilder::new(batcher_valid)
             .batch_size(self.training_config.batch_size)
             .shuffle(self.training_config.seed)
             .num_workers(self.training_config.num_workers)
-            .build(testing_dataset);
-        let learner = LearnerBuilder::new("/tmp/guide")
-            .metric_train_numeric(LossMetric::new())
-            .metric_valid_numeric(LossMetric::new())
-            .with_file_checkpointer(CompactRecorder::new())
-            .devices(vec![self.device.clone()])
-            .num_epochs(self.training_config.num_epochs)
-            .build(
-                self.model.borrow().clone(),
-                self.training_config.optimizer.init(),
-                self.training_config.learning_rate,
+            .build(testing_dataset.clone());
+        let check_every = self
+            .training_config
+            .check_every
+            .unwrap_or(DEFAULT_CHECK_EVERY)
+            .max(1);
+        let time_limit = self.training_config.time_limit.map(Duration::from_secs);
+        // One optimizer for the whole run, so Adam's moments carry over from one round to the next
+        let mut optimizer = self.training_config.optimizer.init::<A, Model<A>>();
+        let mut run = TrainingRun::new(&self.artifact_dir(), self.render_mode)?;
+        // Trains a few epochs at a time and checks after each round whether it can stop
+        let mut model_trained = model;
+        let mut epochs = 0;
+        let (stopped, train_loss, verified_bytes, read_backs) = loop {
+            let round = check_every.min(self.training_config.num_epochs - epochs);
+            model_trained = match head {
+                OutputHead::Classes => self
+                    .fit::<ClassificationOutput<A>, ClassificationOutput<A::InnerBackend>, _>(
+                        model_trained,
+                        round,
+                        &mut optimizer,
+                        &mut run,
+                        &dataloader_train,
+                        &dataloader_test,
+                    )?,
+                _ => self.fit::<RegressionOutput<A>, RegressionOutput<A::InnerBackend>, _>(
+                    model_trained,
+                    round,
+                    &mut optimizer,
+                    &mut run,
+                    &dataloader_train,
+                    &dataloader_test,
+                )?,
+            };
+            epochs += round;
+            let train_loss = dataset_loss(
+                &model_trained.valid(),
+                training_dataset.as_ref(),
+                &self.device,
+            );
+            if !train_loss.is_finite() {
+                return Err(NNError::TrainingDiverged { loss: train_loss });
+            }
+            // Read every range straight back out of the new weights to see how much of it actually stuck
+            let mut read_backs = Vec::new();
+            let mut verified_bytes = 0;
+            for (&offset, buf) in batch.ranges().iter() {
+                let mut read_back = vec![0u8; buf.len()];
+                self.predict(&model_trained, &mut read_back, offset);
+                verified_bytes += read_back
+                    .iter()
+                    .zip(buf.iter())
+                    .filter(|(read, written)| read == written)
+                    .count();
+                read_backs.push(read_back);
+            }
+            let stopped = if verified_bytes == batch.dirty_bytes() {
+                Some(StopReason::Exact)
+            } else if self
+                .training_config
+                .loss_threshold
+                .is_some_and(|threshold| train_loss < threshold)
+            {
+                Some(StopReason::LossThreshold)
+            } else if epochs >= self.training_config.num_epochs {
+                Some(StopReason::MaxEpochs)
+            } else if time_limit.is_some_and(|time_limit| started.elapsed() >= time_limit) {
+                Some(StopReason::TimeLimit)
+            } else {
+                None
+            };
+            if let Some(stopped) = stopped {
+                break (stopped, train_loss, verified_bytes, read_backs);
+            }
+        };
+        let valid_loss = dataset_loss(
+            &model_trained.valid(),
+            testing_dataset.as_ref(),
+            &self.device,
+        );
+        // The table is updated while holding its lock across the swap so readers never see the new weights without their corrections
+        let (corrected_bytes, drifted_bytes) = match &self.corrections {
+            Some(corrections) => {
+                let retrained = self.retrained(&model_trained, &training_dataset, batch, &written);
+                let mut corrections = corrections.lock().unwrap();
+                let corrected_bytes = batch
+                    .ranges()
+                    .iter()
+                    .zip(read_backs.iter())
+                    .map(|((&offset, buf), read_back)| corrections.record(buf, read_back, offset))
+                    .sum();
+                let drifted_bytes = retrained
+                    .into_iter()
+                    .map(|(address, value, prediction)| {
+                        corrections.record(&[value], &[prediction], address)
+                    })
+                    .sum();
+                self.swap_model(model_trained);
+                (corrected_bytes, drifted_bytes)
+            }
+            None => {
+                self.swap_model(model_trained);
+                (0, 0)
+            }
+        };
+        let mut min_confidence = 1.0f32;
+        for (&offset, buf) in batch.ranges().iter() {
+            min_confidence = self
+                .confidence(buf.len(), offset)?
+                .into_iter()
+                .fold(min_confidence, f32::min);
+        }
+        let mut written = self.written.lock().unwrap();
+        let mut history = self.history.lock().unwrap();
+        for (&offset, buf) in batch.ranges().iter() {
+            written.insert(buf.len(), offset);
+            history.push_back((offset, buf.len()));
+        }
+        while history.len() > replay::HISTORY_LEN {
+            history.pop_front();
+        }
+        Ok(TrainReport {
+            epochs,
+            stopped,
+            trained_bytes: batch.dirty_bytes(),
+            train_loss,
+            valid_loss,
+            verified_bytes,
+            corrected_bytes,
+            drifted_bytes,
+            min_confidence,
+            elapsed: started.elapsed(),
+        })
+    }
+
+    // Every address outside the batch that was written before the run and trained on in it, with what it held going in and what the new weights give back for it
+    fn retrained(
+        &self,
+        model: &Model<A>,
+        dataset: &CustomDataset,
+        batch: &Overlay,
+        written: &RangeMap,
+    ) -> Vec<(u64, u8, u8)> {
+        let mut items: Vec<DataItem> = dataset
+            .iter()
+            .filter(|item| written.contains(item.address) && !batch.contains(item.address))
+            .collect();
+        items.sort_unstable_by_key(|item| item.address);
+        items.dedup_by_key(|item| item.address);
+        let addresses: Vec<u64> = items.iter().map(|item| item.address).collect();
+        let mut predicted = vec![0u8; addresses.len()];
+        predict_addresses(&model.valid(), &self.device, &addresses, &mut predicted);
+        items
+            .into_iter()
+            .zip(predicted)
+            .map(|(item, prediction)| (item.address, item.value, prediction))
+            .collect()
+    }
+
+    // The batch plus a sample of what's already stored, so training scales with the write instead of the drive
+    fn replay_dataset(
+        &self,
+        batch: &Overlay,
+        model: &Model<A>,
+        replay_ratio: f64,
+        written: &RangeMap,
+        corrections: Option<&CorrectionTable>,
+    ) -> CustomDataset {
+        let mut items: Vec<DataItem> = batch
+            .ranges()
+            .iter()
+            .flat_map(|(&offset, buf)| {
+                buf.iter().enumerate().map(move |(i, &value)| DataItem {
+                    address: offset + i as u64,
+                    value,
+                })
+            })
+            .collect();
+        // The config is checked against the same bound, this only matters to drives built in code
+        let replay_ratio = replay_ratio.clamp(0.0, MAX_REPLAY_RATIO);
+        let count = (batch.dirty_bytes() as f64 * replay_ratio).round() as usize;
+        let rng = &mut rand::thread_rng();
+        // What reads return for an address today, the weights patched by any correction
+        let stored = |addresses: &[u64]| {
+            let mut values = vec![0u8; addresses.len()];
+            predict_addresses(&model.valid(), &self.device, addresses, &mut values);
+            if let Some(corrections) = corrections {
+                for (value, &address) in values.iter_mut().zip(addresses.iter()) {
+                    *value = corrections.get(address).unwrap_or(*value);
+                }
+            }
+            values
+        };
+        let addresses = match self.training_config.replay_sampling {
+            None | Some(ReplaySampling::Uniform) => replay::uniform(rng, written, batch, count),
+            Some(ReplaySampling::Recency) => {
+                let history = self.history.lock().unwrap();
+                replay::recency(rng, written, batch, &history, count)
+            }
+            Some(ReplaySampling::Error) => {
+                // Draw a wider pool and weight it by how far the raw weights are from what's stored
+                let candidates = replay::uniform(rng, written, batch, count.saturating_mul(4));
+                let mut predicted = vec![0u8; candidates.len()];
+                predict_addresses(&model.valid(), &self.device, &candidates, &mut predicted);
+                let weights: Vec<u32> = predicted
+                    .iter()
+                    .zip(stored(&candidates).iter())
+                    .map(|(&predicted, &stored)| 1 + predicted.abs_diff(stored) as u32)
+                    .collect();
+                replay::weighted(rng, &candidates, &weights, count)
+            }
+        };
+        items.extend(
+            addresses
+                .iter()
+                .zip(stored(&addresses))
+                .map(|(&address, value)| DataItem { address, value }),
+        );
+        CustomDataset::from_items(items)
+    }
+
+    // T and V are what a train and a valid step put out, which depends on the head's loss
+    fn fit<T, V, O>(
+        &self,
+        mut model: Model<A>,
+        epochs: usize,
+        optimizer: &mut O,
+        run: &mut TrainingRun,
+        dataloader_train: &Arc<dyn DataLoader<Batch<A>>>,
+        dataloader_test: &Arc<dyn DataLoader<Batch<A::InnerBackend>>>,
+    ) -> Result<Model<A>, NNError>
+    where
+        Model<A>: TrainStep<Batch<A>, T>,
+        Model<A::InnerBackend>: ValidStep<Batch<A::InnerBackend>, V>,
+        T: Adaptor<LossInput<A>>,
+        V: Adaptor<LossInput<A::InnerBackend>>,
+        O: Optimizer<Model<A>, A>,
+    {
+        let epoch_total = self.training_config.num_epochs;
+        let learning_rate = self.training_config.learning_rate;
+        let checkpoints = self
+            .training_config
+            .checkpoints
+            .unwrap_or(DEFAULT_CHECKPOINTS);
+        // A panic inside burn would otherwise take down the nbdkit worker thread with it
+        panic::catch_unwind(AssertUnwindSafe(|| {
+            for _ in 0..epochs {
+                run.epoch += 1;
+                let epoch = run.epoch;
+                let mut loss = LossMetric::<A>::new();
+                let mut iterator = dataloader_train.iter();
+                let mut iteration = 0;
+                while let Some(batch) = iterator.next() {
+                    iteration += 1;
+                    let output = TrainStep::<_, T>::step(&model, batch);
+                    model = optimizer.step(learning_rate, model, output.grads);
+                    let progress = TrainingProgress {
+                        progress: iterator.progress(),
+                        epoch,
+                        epoch_total,
+                        iteration,
+                    };
+                    let entry = loss.update(
+                        &output.item.adapt(),
+                        &metadata(&progress, Some(learning_rate)),
+                    );
+                    run.train_logger.log(&entry);
+                    run.renderer
+                        .update_train(MetricState::Numeric(entry, loss.value()));
+                    run.renderer.render_train(progress);
+                }
+                run.train_logger.end_epoch(epoch);
+                let model_valid = model.valid();
+                let mut loss = LossMetric::<A::InnerBackend>::new();
+                let mut iterator = dataloader_test.iter();
+                let mut iteration = 0;
+                while let Some(batch) = iterator.next() {
+                    iteration += 1;
+                    let output = ValidStep::<_, V>::step(&model_valid, batch);
+                    let progress = TrainingProgress {
+                        progress: iterator.progress(),
+                        epoch,
+                        epoch_total,
+                        iteration,
+                    };
+                    let entry = loss.update(&output.adapt(), &metadata(&progress, None));
+                    run.valid_logger.log(&entry);
+                    run.renderer
+                        .update_valid(MetricState::Numeric(entry, loss.value()));
+                    run.renderer.render_valid(progress);
+                }
+                run.valid_logger.end_epoch(epoch);
+                if checkpoints > 0 {
+                    run.checkpoint(epoch, checkpoints, &model, optimizer)?;
+                }
+            }
+            Ok(model)
+        }))
+        .map_err(|payload| NNError::Backend(panic_message(payload.as_ref())))?
+    }
+}
+
+// What a training run carries from one round to the next besides the weights and the optimizer, so epochs keep counting and logs and checkpoints follow on
+struct TrainingRun {
+    // Epochs trained so far this run
+    epoch: usize,
+    renderer: Box<dyn MetricsRenderer>,
+    train_logger: FileMetricLogger,
+    valid_logger: FileMetricLogger,
+    artifact_dir: PathBuf,
+    // The newest checkpoint earlier runs left, this run's are numbered on from it
+    checkpoint_base: usize,
+}
+
+impl TrainingRun {
+    fn new(artifact_dir: &Path, render_mode: RenderMode) -> Result<Self, NNError> {
+        fs::create_dir_all(artifact_dir).map_err(|e| NNError::save(artifact_dir, e))?;
+        let artifacts = artifact_dir.to_string_lossy();
+        let checkpoint_dir = artifact_dir.join("checkpoint");
+        let checkpoint_base = checkpoint_numbers(&checkpoint_dir)
+            .map_err(|e| NNError::load(&checkpoint_dir, e))?
+            .into_iter()
+            .map(|(number, _)| number)
+            .max()
+            .unwrap_or(0);
+        Ok(Self {
+            epoch: 0,
+            renderer: render_mode.renderer(),
+            train_logger: FileMetricLogger::new(&format!("{artifacts}/train")),
+            valid_logger: FileMetricLogger::new(&format!("{artifacts}/valid")),
+            artifact_dir: artifact_dir.to_path_buf(),
+            checkpoint_base,
+        })
+    }
+
+    // Keeps the model and optimizer of the last `checkpoints` epochs across every run, named the way burn's own learner names them
+    fn checkpoint<A: AutodiffBackend, O: Optimizer<Model<A>, A>>(
+        &self,
+        epoch: usize,
+        checkpoints: usize,
+        model: &Model<A>,
+        optimizer: &O,
+    ) -> Result<(), NNError> {
+        let number = self.checkpoint_base + epoch;
+        let checkpoint_dir = self.artifact_dir.join("checkpoint");
+        let directory = checkpoint_dir.to_string_lossy();
+        let models = FileCheckpointer::new(CompactRecorder::new(), &directory, "model");
+        let optimizers = FileCheckpointer::new(CompactRecorder::new(), &directory, "optim");
+        let failed = |e| NNError::save(&checkpoint_dir, format!("{:?}", e));
+        Checkpointer::<ModelRecord<A>, A>::save(&models, number, model.clone().into_record())
+            .map_err(failed)?;
+        Checkpointer::<O::Record, A>::save(&optimizers, number, optimizer.to_record())
+            .map_err(failed)?;
+        // Whatever's older, even from a run that kept more of them
+        for (older, path) in
+            checkpoint_numbers(&checkpoint_dir).map_err(|e| NNError::save(&checkpoint_dir, e))?
+        {
+            if older + checkpoints <= number {
+                fs::remove_file(&path).map_err(|e| NNError::save(&path, e))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Every checkpoint file in the directory with the number it was saved under, nothing if there's no directory yet
+fn checkpoint_numbers(checkpoint_dir: &Path) -> io::Result<Vec<(usize, PathBuf)>> {
+    if !checkpoint_dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut numbers = Vec::new();
+    for entry in fs::read_dir(checkpoint_dir)? {
+        let path = entry?.path();
+        let number = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.rsplit_once('-'))
+            .and_then(|(_, number)| number.parse().ok());
+        if let Some(number) = number {
+            numbers.push((number, path));
+        }
+    }
+    Ok(numbers)
+}
+
+fn metadata(progress: &TrainingProgress, lr: Option<f64>) -> MetricMetadata {
+    MetricMetadata {
+        progress: progress.progress.clone(),
+        epoch: progress.epoch,
+        epoch_total: progress.epoch_total,
+        iteration: progress.iteration,
+        lr,
+    }
+}
+
+// The same weights loaded onto a plain inference backend, for serving a drive nobody can write to
+pub struct FrozenNetwork<B: Backend> {
+    // Only locked long enough to clone the model, the weights never change
+    model: Mutex<Model<B>>,
+    corrections: Option<CorrectionTable>,
+    written: RangeMap,
+    checksums: ChecksumTable,
+    read_check: ReadCheck,
+    device: B::Device,
+    max_size: usize,
+}
+
+impl<B: Backend> FrozenNetwork<B> {
+    pub fn open(model_dir: Option<&Path>, mut max_size: usize) -> Result<Self, NNError> {
+        let device = B::Device::default();
+        // With nothing saved there's nothing written either, so the whole drive reads as zero
+        let (model, corrections, written, checksums) = match model_dir {
+            Some(model_dir) if model_dir.join(CONFIG_FILE).exists() => {
+                max_size = load_size(model_dir, max_size)?;
+                let (_, model) = load_weights::<B>(model_dir, &device)?;
+                let (corrections, written) = load_side_tables(model_dir, max_size)?;
+                (model, corrections, written, load_checksums(model_dir)?)
+            }
+            _ => (
+                ModelConfig::new(64, 1).init::<B>(&device),
+                None,
+                RangeMap::default(),
+                ChecksumTable::default(),
+            ),
+        };
+        Ok(Self {
+            model: Mutex::new(model),
+            corrections,
+            written,
+            checksums,
+            read_check: ReadCheck::Off,
+            device,
+            max_size,
+        })
+    }
+
+    pub fn with_read_check(mut self, read_check: ReadCheck) -> Self {
+        self.read_check = read_check;
+        self
+    }
+
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    pub fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<(), NNError> {
+        check_range(buf.len(), offset, self.max_size)?;
+        if matches!(self.read_check, ReadCheck::Off) || buf.is_empty() {
+            self.read_unchecked(buf, offset);
+            return Ok(());
+        }
+        let (start, end) = block_span(buf.len(), offset, self.max_size);
+        let mut blocks = vec![0u8; (end - start) as usize];
+        self.read_unchecked(&mut blocks, start);
+        verify_blocks(&self.checksums, &blocks, start, self.read_check, |_, _| {
+            false
+        })?;
+        let from = (offset - start) as usize;
+        buf.copy_from_slice(&blocks[from..from + buf.len()]);
+        Ok(())
+    }
+
+    fn read_unchecked(&self, buf: &mut [u8], offset: u64) {
+        let model = self.model.lock().unwrap().clone();
+        predict(&model, &self.device, buf, offset);
+        if let Some(corrections) = &self.corrections {
+            corrections.patch(buf, offset);
+        }
+        self.written.zero_unwritten(buf, offset);
+    }
+
+    pub fn extents(&self, len: usize, offset: u64) -> Result<Vec<Extent>, NNError> {
+        check_range(len, offset, self.max_size)?;
+        Ok(self.written.extents(len, offset))
+    }
+}
+
+fn check_range(len: usize, offset: u64, max_size: usize) -> Result<(), NNError> {
+    // Anything past the end of the drive was never part of the training set so we can't answer for it
+    match (offset as usize).checked_add(len) {
+        Some(end) if end <= max_size => Ok(()),
+        _ => Err(NNError::OutOfRange {
+            offset,
+            len,
+            max_size,
+        }),
+    }
+}
+
+// [start, end) of the whole blocks covering a range, the last block stops at the end of the drive
+fn block_span(len: usize, offset: u64, max_size: usize) -> (u64, u64) {
+    let start = offset - offset % CHECKSUM_BLOCK;
+    let end = (offset + len as u64)
+        .next_multiple_of(CHECKSUM_BLOCK)
+        .min(max_size as u64);
+    (start, end)
+}
+
+// Checks every block in `bytes`, which has to start on a block boundary, `skip` says which blocks can't be checked right now
+fn verify_blocks(
+    checksums: &ChecksumTable,
+    bytes: &[u8],
+    start: u64,
+    read_check: ReadCheck,
+    skip: impl Fn(usize, u64) -> bool,
+) -> Result<(), NNError> {
+    for (i, block_bytes) in bytes.chunks(CHECKSUM_BLOCK as usize).enumerate() {
+        let offset = start + i as u64 * CHECKSUM_BLOCK;
+        let Some(checksum) = checksums.get(offset / CHECKSUM_BLOCK) else {
+            continue;
+        };
+        if skip(block_bytes.len(), offset) || crc32c(block_bytes) == checksum {
+            continue;
+        }
+        let mismatch = NNError::ChecksumMismatch {
+            offset,
+            len: block_bytes.len(),
+        };
+        match read_check {
+            ReadCheck::Off => {}
+            ReadCheck::Strict => return Err(mismatch),
+            ReadCheck::BestEffort(sink) => sink(&format!("{}, returning it anyway", mismatch)),
+        }
+    }
+    Ok(())
+}
+
+fn predict<B: Backend>(model: &Model<B>, device: &B::Device, buf: &mut [u8], offset: u64) {
+    let addresses: Vec<u64> = (offset..offset + buf.len() as u64).collect();
+    predict_addresses(model, device, &addresses, buf);
+}
+
+fn predict_addresses<B: Backend>(
+    model: &Model<B>,
+    device: &B::Device,
+    addresses: &[u64],
+    buf: &mut [u8],
+) {
+    if addresses.is_empty() {
+        return;
+    }
+    // let's calculate the bits we need to get: offset * 8
+    let batcher = super::batcher::InternalBatcher::<B>::new(device.clone(), model.head());
+    for (addresses, buf) in addresses
+        .chunks(PREDICT_CHUNK)
+        .zip(buf.chunks_mut(PREDICT_CHUNK))
+    {
+        let batch = batcher.batch(
+            addresses
+                .iter()
+                .map(|&address| super::dataloader::DataItem {
+                    address,
+                    value: 0u8,
+                })
+                .collect(),
+        );
+        buf.copy_from_slice(&model.decode(model.forward(batch.addresses)));
+    }
+}
+
+fn predict_confidence<B: Backend>(
+    model: &Model<B>,
+    device: &B::Device,
+    addresses: &[u64],
+) -> Vec<f32> {
+    let batcher = super::batcher::InternalBatcher::<B>::new(device.clone(), model.head());
+    addresses
+        .chunks(PREDICT_CHUNK)
+        .flat_map(|addresses| {
+            let batch = batcher.batch(
+                addresses
+                    .iter()
+                    .map(|&address| DataItem { address, value: 0 })
+                    .collect(),
             );
-        let model_trained = learner.fit(dataloader_train, dataloader_test);
-        let mut v = self.model.borrow_mut();
-        *v = model_trained;
-        todo!()
+            model.decode_confidence(model.forward(batch.addresses))
+        })
+        .collect()
+}
+
+fn load_weights<B: Backend>(
+    model_dir: &Path,
+    device: &B::Device,
+) -> Result<(TrainingConfig, Model<B>), NNError> {
+    let training_config = TrainingConfig::load(model_dir.join(CONFIG_FILE))
+        .map_err(|e| NNError::load(model_dir, e))?;
+    let path = model_dir.join(MODEL_FILE);
+    let model = match CompactRecorder::new().load::<ModelRecord<B>>(path.clone(), device) {
+        Ok(record) => training_config.model.init::<B>(device).load_record(record),
+        // Drives saved before the stack could be configured have their layers under fixed names
+        Err(e) => match CompactRecorder::new().load::<LegacyModelRecord<B>>(path, device) {
+            Ok(record) => LegacyModel::init(&training_config.model, device)
+                .load_record(record)
+                .into_model(),
+            Err(_) => return Err(NNError::load(model_dir, e)),
+        },
+    };
+    Ok((training_config, model))
+}
+
+// Drives saved before their size was kept take whatever size they're opened with
+fn load_size(model_dir: &Path, max_size: usize) -> Result<usize, NNError> {
+    let size_path = model_dir.join(SIZE_FILE);
+    if !size_path.exists() {
+        return Ok(max_size);
+    }
+    let bytes = fs::read(&size_path).map_err(|e| NNError::load(model_dir, e))?;
+    let size: [u8; 8] = bytes
+        .try_into()
+        .map_err(|_| NNError::load(model_dir, format!("{} is corrupt", SIZE_FILE)))?;
+    usize::try_from(u64::from_le_bytes(size)).map_err(|e| NNError::load(model_dir, e))
+}
+
+fn load_checksums(model_dir: &Path) -> Result<ChecksumTable, NNError> {
+    // Drives saved before blocks were checksummed have nothing to check yet
+    let checksums_path = model_dir.join(CHECKSUMS_FILE);
+    if checksums_path.exists() {
+        ChecksumTable::load(&checksums_path).map_err(|e| NNError::load(model_dir, e))
+    } else {
+        Ok(ChecksumTable::default())
+    }
+}
+
+fn load_side_tables(
+    model_dir: &Path,
+    max_size: usize,
+) -> Result<(Option<CorrectionTable>, RangeMap), NNError> {
+    // A drive that was saved with corrections has to keep them, they hold data the weights don't
+    let corrections_path = model_dir.join(CORRECTIONS_FILE);
+    let corrections = if corrections_path.exists() {
+        Some(CorrectionTable::load(&corrections_path).map_err(|e| NNError::load(model_dir, e))?)
+    } else {
+        None
+    };
+    // Drives saved before writes were tracked have to assume everything holds data
+    let written_path = model_dir.join(WRITTEN_FILE);
+    let written = if written_path.exists() {
+        RangeMap::load(&written_path).map_err(|e| NNError::load(model_dir, e))?
+    } else {
+        RangeMap::full(max_size)
+    };
+    Ok((corrections, written))
+}
+
+fn dataset_loss<B: Backend>(model: &Model<B>, dataset: &CustomDataset, device: &B::Device) -> f32 {
+    let batcher = batcher::InternalBatcher::<B>::new(device.clone(), model.head());
+    let items: Vec<DataItem> = dataset.iter().collect();
+    // In chunks, the class head's 256 outputs per byte would never fit for a whole drive at once
+    let total: f32 = items
+        .chunks(PREDICT_CHUNK)
+        .map(|items| {
+            let loss = model.forward_loss(batcher.batch(items.to_vec()));
+            loss.into_scalar().elem::<f32>() * items.len() as f32
+        })
+        .sum();
+    total / items.len().max(1) as f32
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => payload
+            .downcast_ref::<String>()
+            .cloned()
+            .unwrap_or_else(|| "unknown panic".to_string()),
+    }
+}
+
+fn save_side_table(
+    model_dir: &Path,
+    name: &str,
+    save: impl FnOnce(&Path) -> io::Result<()>,
+) -> Result<(), NNError> {
+    let staged = model_dir.join(format!("{name}-staged"));
+    save(&staged)
+        .and_then(|_| commit_file(&staged, &model_dir.join(name)))
+        .map_err(|e| NNError::save(model_dir, e))
+}
+
+fn commit_file(staged: &Path, path: &Path) -> io::Result<()> {
+    File::open(staged)?.sync_all()?;
+    fs::rename(staged, path)
+}
+
+#[cfg(all(test, feature = "backend-ndarray"))]
+mod tests {
+    use burn::backend::{Autodiff, NdArray};
+
+    use super::*;
+
+    type TestNetwork = TheNetwork<Autodiff<NdArray<f32>>>;
+
+    #[test]
+    fn untrained_writes_are_allocated() {
+        let network =
+            TestNetwork::init(1024, ModelConfig::new(64, 1)).with_writeback_threshold(usize::MAX);
+        assert!(network.write(&[1, 2, 3, 4], 100).unwrap().is_none());
+        assert_eq!(
+            network.extents(200, 0).unwrap(),
+            vec![
+                Extent {
+                    offset: 0,
+                    len: 100,
+                    written: false,
+                },
+                Extent {
+                    offset: 100,
+                    len: 4,
+                    written: true,
+                },
+                Extent {
+                    offset: 104,
+                    len: 96,
+                    written: false,
+                },
+            ]
+        );
+        assert_eq!(network.written_bytes(), 0);
+    }
+
+    #[test]
+    fn checkpoints_carry_on_from_earlier_runs() {
+        let artifact_dir = env::temp_dir().join(format!("checkpoints-{}-prune", process::id()));
+        fs::remove_dir_all(&artifact_dir).ok();
+        let checkpoint_dir = artifact_dir.join("checkpoint");
+        fs::create_dir_all(&checkpoint_dir).unwrap();
+        // Left by earlier runs, one of them keeping more than this one does
+        for name in ["model-1.mpk", "optim-1.mpk", "model-7.mpk", "optim-7.mpk"] {
+            fs::write(checkpoint_dir.join(name), []).unwrap();
+        }
+        let run = TrainingRun::new(&artifact_dir, RenderMode::Quiet).unwrap();
+        let model = ModelConfig::new(64, 1).init::<Autodiff<NdArray<f32>>>(&Default::default());
+        let optimizer = AdamConfig::new().init::<Autodiff<NdArray<f32>>, _>();
+        run.checkpoint(1, 2, &model, &optimizer).unwrap();
+        let mut names: Vec<String> = fs::read_dir(&checkpoint_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            ["model-7.mpk", "model-8.mpk", "optim-7.mpk", "optim-8.mpk"]
+        );
+        fs::remove_dir_all(&artifact_dir).ok();
     }
 }
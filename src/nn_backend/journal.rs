@@ -0,0 +1,334 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use super::{checksums::crc32c, overlay::Overlay};
+
+// Each record starts with a tag byte, a little endian u64 offset, a little endian u64 length and a little endian u32 checksum of all that plus the bytes
+const HEADER_LEN: usize = 21;
+const TAG_WRITE: u8 = 0;
+const TAG_TRIM: u8 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Record {
+    Write { offset: u64, bytes: Vec<u8> },
+    Trim { offset: u64, len: usize },
+}
+
+// Writes and trims that have been acknowledged but aren't in the saved weights yet, in the order they happened
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+    // Only opened on the first append so an export isn't created until something is written to it
+    file: Option<File>,
+    // Set for a drive that isn't journaled any more, the first compaction that leaves nothing pending removes the journal
+    retire_when_empty: bool,
+    // Removed by that compaction, nothing is appended after it
+    retired: bool,
+}
+
+impl Journal {
+    // Reads back whatever an earlier run left behind, a record cut short by a crash was never acknowledged and is dropped
+    pub fn open(path: &Path) -> io::Result<(Self, Vec<Record>)> {
+        let mut records = Vec::new();
+        let mut file = None;
+        if path.exists() {
+            let mut reader = BufReader::new(File::open(path)?);
+            let mut intact = 0;
+            while let Some(record) = read_record(&mut reader)? {
+                intact += record_len(&record) as u64;
+                records.push(record);
+            }
+            let appender = OpenOptions::new().append(true).open(path)?;
+            if appender.metadata()?.len() > intact {
+                appender.set_len(intact)?;
+                appender.sync_all()?;
+            }
+            file = Some(appender);
+        }
+        let journal = Self {
+            path: path.to_path_buf(),
+            file,
+            retire_when_empty: false,
+            retired: false,
+        };
+        Ok((journal, records))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn retire_when_empty(&mut self) {
+        self.retire_when_empty = true;
+    }
+
+    pub fn append_write(&mut self, buf: &[u8], offset: u64) -> io::Result<()> {
+        self.append(TAG_WRITE, offset, buf.len(), buf)
+    }
+
+    pub fn append_trim(&mut self, len: usize, offset: u64) -> io::Result<()> {
+        self.append(TAG_TRIM, offset, len, &[])
+    }
+
+    // The record is on disk by the time this returns
+    fn append(&mut self, tag: u8, offset: u64, len: usize, bytes: &[u8]) -> io::Result<()> {
+        if self.retired {
+            return Ok(());
+        }
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => self.file.insert(create(&self.path)?),
+        };
+        file.write_all(&encode(tag, offset, len, bytes))?;
+        file.sync_data()
+    }
+
+    // Called once the weights that absorb everything journaled so far are saved, anything still waiting to be trained in is written back out
+    pub fn compact(&mut self, pending: &Overlay) -> io::Result<()> {
+        let Some(file) = &mut self.file else {
+            return Ok(());
+        };
+        if pending.is_empty() && self.retire_when_empty {
+            fs::remove_file(&self.path)?;
+            self.file = None;
+            self.retired = true;
+            return sync_dir(self.path.parent().unwrap_or(Path::new("")));
+        }
+        if pending.is_empty() {
+            file.set_len(0)?;
+            return file.sync_all();
+        }
+        let mut staged = self.path.clone().into_os_string();
+        staged.push("-staged");
+        let mut writer = BufWriter::new(File::create(&staged)?);
+        for (&offset, bytes) in pending.ranges().iter() {
+            writer.write_all(&encode(TAG_WRITE, offset, bytes.len(), bytes))?;
+        }
+        writer.into_inner()?.sync_all()?;
+        fs::rename(&staged, &self.path)?;
+        if let Some(parent) = self.path.parent() {
+            File::open(parent)?.sync_all()?;
+        }
+        self.file = Some(OpenOptions::new().append(true).open(&self.path)?);
+        Ok(())
+    }
+}
+
+// Makes the journal and any directories it needs, a record in it is only durable once every new entry on the way to it is too
+fn create(path: &Path) -> io::Result<File> {
+    let parent = path.parent().unwrap_or(Path::new(""));
+    let missing: Vec<PathBuf> = parent
+        .ancestors()
+        .take_while(|dir| !dir.as_os_str().is_empty() && !dir.exists())
+        .map(Path::to_path_buf)
+        .collect();
+    fs::create_dir_all(parent)?;
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    sync_dir(parent)?;
+    for dir in missing {
+        sync_dir(dir.parent().unwrap_or(Path::new("")))?;
+    }
+    Ok(file)
+}
+
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    File::open(dir)?.sync_all()
+}
+
+fn encode(tag: u8, offset: u64, len: usize, bytes: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(HEADER_LEN + bytes.len());
+    record.push(tag);
+    record.extend_from_slice(&offset.to_le_bytes());
+    record.extend_from_slice(&(len as u64).to_le_bytes());
+    record.extend_from_slice(&[0; 4]);
+    record.extend_from_slice(bytes);
+    let checksum = record_checksum(&record);
+    record[17..HEADER_LEN].copy_from_slice(&checksum.to_le_bytes());
+    record
+}
+
+// Covers the header and the bytes with the checksum field itself zeroed
+fn record_checksum(record: &[u8]) -> u32 {
+    let mut zeroed = record.to_vec();
+    zeroed[17..HEADER_LEN].fill(0);
+    crc32c(&zeroed)
+}
+
+fn record_len(record: &Record) -> usize {
+    match record {
+        Record::Write { bytes, .. } => HEADER_LEN + bytes.len(),
+        Record::Trim { .. } => HEADER_LEN,
+    }
+}
+
+// None at the end of the journal or at the first record that didn't make it to disk whole
+fn read_record(reader: &mut impl Read) -> io::Result<Option<Record>> {
+    let mut header = [0u8; HEADER_LEN];
+    match reader.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let tag = header[0];
+    let offset = u64::from_le_bytes(header[1..9].try_into().unwrap());
+    let len = u64::from_le_bytes(header[9..17].try_into().unwrap()) as usize;
+    let checksum = u32::from_le_bytes(header[17..HEADER_LEN].try_into().unwrap());
+    let mut record = header.to_vec();
+    if tag == TAG_WRITE {
+        let mut bytes = Vec::new();
+        reader.take(len as u64).read_to_end(&mut bytes)?;
+        if bytes.len() < len {
+            return Ok(None);
+        }
+        record.extend_from_slice(&bytes);
+    } else if tag != TAG_TRIM {
+        return Ok(None);
+    }
+    if record_checksum(&record) != checksum {
+        return Ok(None);
+    }
+    Ok(Some(match tag {
+        TAG_WRITE => Record::Write {
+            offset,
+            bytes: record.split_off(HEADER_LEN),
+        },
+        _ => Record::Trim { offset, len },
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("journal-{}-{}", process::id(), name));
+        fs::remove_dir_all(&dir).ok();
+        dir
+    }
+
+    fn write(offset: u64, bytes: &[u8]) -> Record {
+        Record::Write {
+            offset,
+            bytes: bytes.to_vec(),
+        }
+    }
+
+    #[test]
+    fn replays_what_was_appended() {
+        let path = scratch_dir("replay").join("export").join("journal.bin");
+        let (mut journal, records) = Journal::open(&path).unwrap();
+        assert!(records.is_empty());
+        assert!(!path.exists());
+        journal.append_write(&[1, 2, 3], 10).unwrap();
+        journal.append_trim(4, 11).unwrap();
+        journal.append_write(&[], 7).unwrap();
+        let (_, records) = Journal::open(&path).unwrap();
+        assert_eq!(
+            records,
+            vec![
+                write(10, &[1, 2, 3]),
+                Record::Trim { offset: 11, len: 4 },
+                write(7, &[]),
+            ]
+        );
+    }
+
+    #[test]
+    fn drops_a_truncated_record() {
+        let path = scratch_dir("truncated").join("journal.bin");
+        let (mut journal, _) = Journal::open(&path).unwrap();
+        journal.append_write(&[1, 2, 3], 0).unwrap();
+        let intact = (HEADER_LEN + 3) as u64;
+        // Cut short in the middle of the second record's bytes, then in the middle of its header
+        for cut in [intact + HEADER_LEN as u64 + 2, intact + 5] {
+            journal.append_write(&[4, 5, 6, 7], 100).unwrap();
+            let file = OpenOptions::new().write(true).open(&path).unwrap();
+            file.set_len(cut).unwrap();
+            let (reopened, records) = Journal::open(&path).unwrap();
+            assert_eq!(records, vec![write(0, &[1, 2, 3])]);
+            assert_eq!(fs::metadata(&path).unwrap().len(), intact);
+            journal = reopened;
+        }
+        // Appending after the torn tail was cut off lands right behind the last good record
+        journal.append_trim(1, 2).unwrap();
+        let (_, records) = Journal::open(&path).unwrap();
+        assert_eq!(
+            records,
+            vec![write(0, &[1, 2, 3]), Record::Trim { offset: 2, len: 1 }]
+        );
+    }
+
+    #[test]
+    fn stops_at_a_corrupted_checksum() {
+        let path = scratch_dir("corrupted").join("journal.bin");
+        let (mut journal, _) = Journal::open(&path).unwrap();
+        journal.append_write(&[1, 2, 3], 0).unwrap();
+        journal.append_write(&[4, 5, 6], 50).unwrap();
+        journal.append_write(&[7, 8, 9], 90).unwrap();
+        let mut bytes = fs::read(&path).unwrap();
+        let second = HEADER_LEN + 3;
+        bytes[second + HEADER_LEN + 1] ^= 0xff;
+        fs::write(&path, &bytes).unwrap();
+        // Nothing after the bad record can be trusted either, so it all goes
+        let (_, records) = Journal::open(&path).unwrap();
+        assert_eq!(records, vec![write(0, &[1, 2, 3])]);
+        assert_eq!(fs::metadata(&path).unwrap().len(), second as u64);
+    }
+
+    #[test]
+    fn compaction_keeps_only_pending_writes() {
+        let path = scratch_dir("compact").join("journal.bin");
+        let (mut journal, _) = Journal::open(&path).unwrap();
+        journal.append_write(&[1, 2, 3], 0).unwrap();
+        journal.append_trim(2, 1).unwrap();
+        journal.append_write(&[4, 5], 20).unwrap();
+        let mut pending = Overlay::default();
+        pending.insert(&[4, 5], 20);
+        pending.insert(&[6], 22);
+        journal.compact(&pending).unwrap();
+        let (_, records) = Journal::open(&path).unwrap();
+        assert_eq!(records, vec![write(20, &[4, 5, 6])]);
+        // Still appends to the compacted journal rather than the file it replaced
+        journal.append_write(&[9], 40).unwrap();
+        let (mut journal, records) = Journal::open(&path).unwrap();
+        assert_eq!(records, vec![write(20, &[4, 5, 6]), write(40, &[9])]);
+        journal.compact(&Overlay::default()).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+        let (_, records) = Journal::open(&path).unwrap();
+        assert!(records.is_empty());
+    }
+
+    #[test]
+    fn retired_journal_is_removed_once_nothing_is_pending() {
+        let path = scratch_dir("retire").join("journal.bin");
+        let (mut journal, _) = Journal::open(&path).unwrap();
+        journal.append_write(&[1, 2], 0).unwrap();
+        let (mut journal, records) = Journal::open(&path).unwrap();
+        assert_eq!(records, vec![write(0, &[1, 2])]);
+        journal.retire_when_empty();
+        // Still kept up while the save leaves something pending
+        let mut pending = Overlay::default();
+        pending.insert(&[3], 5);
+        journal.compact(&pending).unwrap();
+        journal.append_trim(1, 5).unwrap();
+        let (_, records) = Journal::open(&path).unwrap();
+        assert_eq!(
+            records,
+            vec![write(5, &[3]), Record::Trim { offset: 5, len: 1 }]
+        );
+        journal.compact(&Overlay::default()).unwrap();
+        assert!(!path.exists());
+        journal.append_write(&[4], 9).unwrap();
+        assert!(!path.exists());
+    }
+}
@@ -1,5 +1,14 @@
 pub mod batcher;
+pub mod checksums;
+pub mod corrections;
 pub mod dataloader;
 pub mod interface;
+pub mod journal;
 pub mod model;
+pub mod overlay;
+pub mod ranges;
+pub mod renderer;
+pub mod replay;
+pub mod snapshots;
+pub mod table;
 pub mod trainer;
@@ -1,77 +1,279 @@
 use burn::{
+    config::Config,
+    constant,
     module::Module,
-    nn::{loss::MseLoss, Linear, LinearConfig, Relu},
+    nn::{
+        loss::{CrossEntropyLossConfig, MseLoss},
+        Dropout, DropoutConfig, Linear, LinearConfig, Relu,
+    },
     tensor::{
+        activation,
         backend::{AutodiffBackend, Backend},
-        Tensor,
+        ElementConversion, Tensor,
     },
-    train::{RegressionOutput, TrainOutput, TrainStep, ValidStep},
+    train::{ClassificationOutput, RegressionOutput, TrainOutput, TrainStep, ValidStep},
 };
 
 use super::batcher::Batch;
 
+// The hidden stack every drive had before it could be configured
+pub const DEFAULT_HIDDEN: [usize; 7] = [32, 128, 128, 128, 128, 512, 128];
+// Width of each hidden layer when only a depth is given
+pub const DEFAULT_WIDTH: usize = 128;
+
+#[derive(Config, Debug, Copy, PartialEq, Eq)]
+pub enum Activation {
+    Relu,
+    Gelu,
+    Silu,
+    // Periodic, can suit the bit patterns of addresses better than the rectifiers
+    Sine,
+}
+
+// Carried in the model as a plain value, it has no weights
+constant!(Activation);
+
+// What the last layer's outputs are squashed into and how a byte is read back out of them
+#[derive(Config, Debug, Copy, PartialEq, Eq)]
+pub enum OutputHead {
+    // ReLU over the raw byte value, unbounded above, what every drive had before heads could be picked
+    Relu,
+    // Sigmoid scaled up to 0..=255
+    Sigmoid,
+    // Linear scaled up to 0..=255, clamped only when it's read back
+    Clamped,
+    // One sigmoid per bit, least significant first
+    Bits,
+    // A logit for each of the 256 values, trained with cross-entropy
+    Classes,
+}
+
+constant!(OutputHead);
+
+impl OutputHead {
+    // Outputs per byte
+    pub fn width(&self) -> usize {
+        match self {
+            OutputHead::Bits => 8,
+            OutputHead::Classes => 256,
+            _ => 1,
+        }
+    }
+
+    // Targets per byte, the class head is trained toward the byte's value as a class index
+    pub fn target_width(&self) -> usize {
+        match self {
+            OutputHead::Classes => 1,
+            _ => self.width(),
+        }
+    }
+
+    pub fn forward<B: Backend, const D: usize>(&self, x: Tensor<B, D>) -> Tensor<B, D> {
+        match self {
+            OutputHead::Relu => activation::relu(x),
+            OutputHead::Sigmoid | OutputHead::Bits => activation::sigmoid(x),
+            // Trained on the raw output, a clamp here would have no gradient to pull back an overshoot with
+            OutputHead::Clamped => x,
+            // Left as logits, cross-entropy does its own softmax
+            OutputHead::Classes => x,
+        }
+    }
+
+    // What the head is trained toward for a byte, in the range it outputs
+    pub fn targets(&self, value: u8, targets: &mut Vec<f32>) {
+        match self {
+            OutputHead::Relu | OutputHead::Classes => targets.push(value as f32),
+            OutputHead::Sigmoid | OutputHead::Clamped => targets.push(value as f32 / 255.0),
+            OutputHead::Bits => targets.extend((0..8).map(|bit| ((value >> bit) & 1) as f32)),
+        }
+    }
+
+    // Rounds to the nearest byte, anything out of range saturates
+    pub fn decode(&self, outputs: &[f32]) -> u8 {
+        match self {
+            OutputHead::Relu => outputs[0].round().clamp(0.0, 255.0) as u8,
+            OutputHead::Sigmoid | OutputHead::Clamped => {
+                (outputs[0] * 255.0).round().clamp(0.0, 255.0) as u8
+            }
+            OutputHead::Bits => outputs
+                .iter()
+                .enumerate()
+                .filter(|(_, &bit)| bit >= 0.5)
+                .fold(0u8, |byte, (i, _)| byte | (1 << i)),
+            OutputHead::Classes => {
+                outputs
+                    .iter()
+                    .enumerate()
+                    .fold((0, f32::NEG_INFINITY), |best, (i, &logit)| {
+                        if logit > best.1 {
+                            (i, logit)
+                        } else {
+                            best
+                        }
+                    })
+                    .0 as u8
+            }
+        }
+    }
+
+    // How sure the weights are of the byte they decode to, from 0 on the fence to 1 certain
+    pub fn confidence(&self, outputs: &[f32]) -> f32 {
+        // How far a scaled output sits from halfway between two bytes
+        let settled = |value: f32| 1.0 - 2.0 * (value - value.round()).abs();
+        match self {
+            OutputHead::Relu => settled(outputs[0]),
+            OutputHead::Sigmoid | OutputHead::Clamped => {
+                settled((outputs[0] * 255.0).clamp(0.0, 255.0))
+            }
+            OutputHead::Bits => outputs
+                .iter()
+                .map(|&bit| (2.0 * bit - 1.0).abs())
+                .fold(1.0, f32::min),
+            // The softmax probability of the winning class
+            OutputHead::Classes => {
+                let max = outputs.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+                1.0 / outputs
+                    .iter()
+                    .map(|&logit| (logit - max).exp())
+                    .sum::<f32>()
+            }
+        }
+    }
+}
+
+impl Activation {
+    pub fn forward<B: Backend, const D: usize>(&self, x: Tensor<B, D>) -> Tensor<B, D> {
+        match self {
+            Activation::Relu => activation::relu(x),
+            Activation::Gelu => activation::gelu(x),
+            Activation::Silu => activation::silu(x),
+            Activation::Sine => x.sin(),
+        }
+    }
+}
+
 #[derive(Module, Debug)]
 pub struct Model<B: Backend> {
-    lin1: Linear<B>,
-    lin2: Linear<B>,
-    linrep1: Linear<B>,
-    linrep2: Linear<B>,
-    linrep3: Linear<B>,
-    lin3: Linear<B>,
-    lin4: Linear<B>,
-    lin5: Linear<B>,
-    activation: Relu,
+    hidden: Vec<Linear<B>>,
+    output: Linear<B>,
+    activation: Activation,
+    // Adds each hidden layer's input to its output wherever the widths match
+    residual: bool,
+    // Only does anything on an autodiff backend, so inference has to go through valid()
+    dropout: Dropout,
+    head: OutputHead,
 }
 
-#[derive(burn::config::Config, Debug, Default)]
+// Every field is optional so configs saved before the architecture could be chosen still load as the stack they were trained with
+#[derive(Config, Debug, Default)]
 pub struct ModelConfig {
     pub input_size: usize,
     pub output_size: usize,
+    // Width of every hidden layer in order, takes precedence over depth
+    pub hidden: Option<Vec<usize>>,
+    // Number of DEFAULT_WIDTH hidden layers, None with no hidden widths keeps DEFAULT_HIDDEN
+    pub depth: Option<usize>,
+    // None is ReLU
+    pub activation: Option<Activation>,
+    pub residual: Option<bool>,
+    // Chance of zeroing each hidden activation while training, None is no dropout
+    pub dropout: Option<f64>,
+    // None is the ReLU head drives were saved with before heads could be picked
+    pub head: Option<OutputHead>,
 }
 
 impl ModelConfig {
+    pub fn hidden_widths(&self) -> Vec<usize> {
+        match (&self.hidden, self.depth) {
+            (Some(hidden), _) => hidden.clone(),
+            (None, Some(depth)) => vec![DEFAULT_WIDTH; depth],
+            (None, None) => DEFAULT_HIDDEN.to_vec(),
+        }
+    }
+
+    pub fn head(&self) -> OutputHead {
+        self.head.unwrap_or(OutputHead::Relu)
+    }
+
     pub fn init<B: Backend>(&self, device: &B::Device) -> Model<B> {
+        let widths = self.hidden_widths();
+        let mut input_size = self.input_size;
+        let mut hidden = Vec::with_capacity(widths.len());
+        for &width in widths.iter() {
+            hidden.push(
+                LinearConfig::new(input_size, width)
+                    .with_bias(true)
+                    .init(device),
+            );
+            input_size = width;
+        }
         Model {
-            lin1: LinearConfig::new(self.input_size, 32)
-                .with_bias(true)
-                .init(device),
-            activation: Relu::new(),
-            lin2: LinearConfig::new(32, 128).with_bias(true).init(device),
-            linrep1: LinearConfig::new(128, 128).with_bias(true).init(device),
-            linrep2: LinearConfig::new(128, 128).with_bias(true).init(device),
-            linrep3: LinearConfig::new(128, 128).with_bias(true).init(device),
-            lin4: LinearConfig::new(128, 512).with_bias(true).init(device),
-            lin5: LinearConfig::new(512, 128).with_bias(true).init(device),
-            lin3: LinearConfig::new(128, self.output_size)
+            hidden,
+            output: LinearConfig::new(input_size, self.output_size * self.head().width())
                 .with_bias(true)
                 .init(device),
+            activation: self.activation.unwrap_or(Activation::Relu),
+            residual: self.residual.unwrap_or(false),
+            dropout: DropoutConfig::new(self.dropout.unwrap_or(0.0)).init(),
+            head: self.head(),
         }
     }
 }
 
 impl<B: Backend> Model<B> {
     pub fn forward(&self, input: Tensor<B, 2>) -> Tensor<B, 2> {
-        let x = input.detach();
-        let x = self.lin1.forward(x);
-        let x = self.activation.forward(x);
-        let x = self.lin2.forward(x);
-        let x = self.activation.forward(x);
-        let x = self.linrep1.forward(x);
-        let x = self.activation.forward(x);
-        let x = self.linrep2.forward(x);
-        let x = self.activation.forward(x);
-        let x = self.linrep3.forward(x);
-        let x = self.activation.forward(x);
-        let x = self.lin4.forward(x);
-        let x = self.activation.forward(x);
-        let x = self.lin5.forward(x);
-        let x = self.activation.forward(x);
-        let x = self.lin3.forward(x);
-        self.activation.forward(x) // Would be better if it clamped values to (0,1)
+        let mut x = input.detach();
+        for layer in self.hidden.iter() {
+            let y = self.activation.forward(layer.forward(x.clone()));
+            let y = self.dropout.forward(y);
+            x = if self.residual && y.dims() == x.dims() {
+                x + y
+            } else {
+                y
+            };
+        }
+        self.head.forward(self.output.forward(x))
+    }
+
+    pub fn head(&self) -> OutputHead {
+        self.head
+    }
+
+    // One byte per row of the output
+    pub fn decode(&self, output: Tensor<B, 2>) -> Vec<u8> {
+        rows(output)
+            .chunks(self.head.width())
+            .map(|outputs| self.head.decode(outputs))
+            .collect()
+    }
+
+    // How sure the weights are of each decoded byte, see OutputHead::confidence
+    pub fn decode_confidence(&self, output: Tensor<B, 2>) -> Vec<f32> {
+        rows(output)
+            .chunks(self.head.width())
+            .map(|outputs| self.head.confidence(outputs))
+            .collect()
+    }
+
+    // The mean loss over a batch under whichever loss the head trains with
+    pub fn forward_loss(&self, item: Batch<B>) -> Tensor<B, 1> {
+        match self.head {
+            OutputHead::Classes => self.forward_classification(item).loss,
+            _ => self.forward_regression(item).loss,
+        }
+    }
+
+    pub fn forward_classification(&self, item: Batch<B>) -> ClassificationOutput<B> {
+        let targets = item.targets.squeeze::<1>(1).int();
+        let output = self.forward(item.addresses);
+        let loss = CrossEntropyLossConfig::new()
+            .init(&output.device())
+            .forward(output.clone(), targets.clone());
+        ClassificationOutput::new(loss, output, targets)
     }
 
     pub fn forward_regression(&self, item: Batch<B>) -> RegressionOutput<B> {
-        let targets: Tensor<B, 2> = item.targets.unsqueeze();
+        let targets = item.targets;
         let output: Tensor<B, 2> = self.forward(item.addresses);
         let loss = MseLoss::new().forward(
             output.clone(),
@@ -82,6 +284,70 @@ impl<B: Backend> Model<B> {
     }
 }
 
+fn rows<B: Backend>(output: Tensor<B, 2>) -> Vec<f32> {
+    output
+        .into_data()
+        .value
+        .into_iter()
+        .map(|v| v.elem::<f32>())
+        .collect()
+}
+
+// The fixed layout weights were saved in before the stack could be configured, only ever loaded and converted
+#[derive(Module, Debug)]
+pub struct LegacyModel<B: Backend> {
+    lin1: Linear<B>,
+    lin2: Linear<B>,
+    linrep1: Linear<B>,
+    linrep2: Linear<B>,
+    linrep3: Linear<B>,
+    lin3: Linear<B>,
+    lin4: Linear<B>,
+    lin5: Linear<B>,
+    activation: Relu,
+}
+
+impl<B: Backend> LegacyModel<B> {
+    pub fn init(config: &ModelConfig, device: &B::Device) -> Self {
+        let linear = |input, output| {
+            LinearConfig::new(input, output)
+                .with_bias(true)
+                .init(device)
+        };
+        Self {
+            lin1: linear(config.input_size, 32),
+            lin2: linear(32, 128),
+            linrep1: linear(128, 128),
+            linrep2: linear(128, 128),
+            linrep3: linear(128, 128),
+            lin3: linear(128, config.output_size),
+            lin4: linear(128, 512),
+            lin5: linear(512, 128),
+            activation: Relu::new(),
+        }
+    }
+
+    // Same layers in the order the old forward pass ran them
+    pub fn into_model(self) -> Model<B> {
+        Model {
+            hidden: vec![
+                self.lin1,
+                self.lin2,
+                self.linrep1,
+                self.linrep2,
+                self.linrep3,
+                self.lin4,
+                self.lin5,
+            ],
+            output: self.lin3,
+            activation: Activation::Relu,
+            residual: false,
+            dropout: DropoutConfig::new(0.0).init(),
+            head: OutputHead::Relu,
+        }
+    }
+}
+
 impl<B: AutodiffBackend> TrainStep<super::batcher::Batch<B>, RegressionOutput<B>> for Model<B> {
     fn step(
         &self,
@@ -96,3 +362,106 @@ impl<B: Backend> ValidStep<super::batcher::Batch<B>, RegressionOutput<B>> for Mo
         self.forward_regression(item)
     }
 }
+
+impl<B: AutodiffBackend> TrainStep<super::batcher::Batch<B>, ClassificationOutput<B>> for Model<B> {
+    fn step(
+        &self,
+        item: super::batcher::Batch<B>,
+    ) -> burn::train::TrainOutput<ClassificationOutput<B>> {
+        let item = self.forward_classification(item);
+        TrainOutput::new(self, item.loss.backward(), item)
+    }
+}
+impl<B: Backend> ValidStep<super::batcher::Batch<B>, ClassificationOutput<B>> for Model<B> {
+    fn step(&self, item: super::batcher::Batch<B>) -> ClassificationOutput<B> {
+        self.forward_classification(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADS: [OutputHead; 5] = [
+        OutputHead::Relu,
+        OutputHead::Sigmoid,
+        OutputHead::Clamped,
+        OutputHead::Bits,
+        OutputHead::Classes,
+    ];
+
+    // What a head would output for a byte it has learned exactly
+    fn outputs(head: OutputHead, value: u8) -> Vec<f32> {
+        let mut targets = Vec::new();
+        head.targets(value, &mut targets);
+        assert_eq!(targets.len(), head.target_width());
+        match head {
+            OutputHead::Classes => {
+                let mut logits = vec![0.0; head.width()];
+                logits[targets[0] as usize] = 10.0;
+                logits
+            }
+            _ => targets,
+        }
+    }
+
+    #[test]
+    fn every_byte_round_trips_through_each_head() {
+        for head in HEADS {
+            for value in 0..=255u8 {
+                assert_eq!(head.decode(&outputs(head, value)), value, "{head:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn decode_rounds_to_the_nearest_byte() {
+        assert_eq!(OutputHead::Relu.decode(&[254.9]), 255);
+        assert_eq!(OutputHead::Relu.decode(&[3.4]), 3);
+        for head in [OutputHead::Sigmoid, OutputHead::Clamped] {
+            assert_eq!(head.decode(&[254.9 / 255.0]), 255);
+            assert_eq!(head.decode(&[3.4 / 255.0]), 3);
+        }
+    }
+
+    #[test]
+    fn decode_saturates_out_of_range_outputs() {
+        assert_eq!(OutputHead::Relu.decode(&[300.0]), 255);
+        assert_eq!(OutputHead::Relu.decode(&[-4.0]), 0);
+        for head in [OutputHead::Sigmoid, OutputHead::Clamped] {
+            assert_eq!(head.decode(&[1.7]), 255);
+            assert_eq!(head.decode(&[-0.3]), 0);
+        }
+    }
+
+    #[test]
+    fn bits_are_least_significant_first() {
+        let mut targets = Vec::new();
+        OutputHead::Bits.targets(0b0000_0110, &mut targets);
+        assert_eq!(targets, [0.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+        assert_eq!(
+            OutputHead::Bits.decode(&[0.9, 0.1, 0.2, 0.0, 0.0, 0.0, 0.0, 0.6]),
+            0b1000_0001
+        );
+    }
+
+    #[test]
+    fn confidence_is_how_far_from_the_fence_an_output_sits() {
+        assert_eq!(OutputHead::Relu.confidence(&[7.0]), 1.0);
+        assert_eq!(OutputHead::Relu.confidence(&[7.5]), 0.0);
+        assert!((OutputHead::Relu.confidence(&[254.9]) - 0.8).abs() < 1e-3);
+        // Past either end it can only decode to that end
+        assert_eq!(OutputHead::Clamped.confidence(&[1.7]), 1.0);
+        assert_eq!(OutputHead::Sigmoid.confidence(&[-0.3]), 1.0);
+        assert_eq!(
+            OutputHead::Bits.confidence(&[1.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0]),
+            1.0
+        );
+        assert_eq!(
+            OutputHead::Bits.confidence(&[1.0, 0.5, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0]),
+            0.0
+        );
+        assert!((OutputHead::Classes.confidence(&[0.0; 256]) - 1.0 / 256.0).abs() < 1e-6);
+        assert!(OutputHead::Classes.confidence(&outputs(OutputHead::Classes, 9)) > 0.9);
+    }
+}
@@ -0,0 +1,219 @@
+use std::collections::BTreeMap;
+
+#[derive(Debug, Default, Clone)]
+pub struct Overlay {
+    // Start -> the bytes written there, never overlapping or touching
+    ranges: BTreeMap<u64, Vec<u8>>,
+}
+
+impl Overlay {
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn dirty_bytes(&self) -> usize {
+        self.ranges.values().map(|bytes| bytes.len()).sum()
+    }
+
+    pub fn ranges(&self) -> &BTreeMap<u64, Vec<u8>> {
+        &self.ranges
+    }
+
+    pub fn contains(&self, address: u64) -> bool {
+        self.ranges
+            .range(..=address)
+            .next_back()
+            .is_some_and(|(&start, bytes)| address < start + bytes.len() as u64)
+    }
+
+    pub fn overlaps(&self, len: usize, offset: u64) -> bool {
+        let end = offset + len as u64;
+        self.ranges
+            .range(..end)
+            .next_back()
+            .is_some_and(|(&start, bytes)| start + bytes.len() as u64 > offset)
+    }
+
+    pub fn insert(&mut self, buf: &[u8], offset: u64) {
+        if buf.is_empty() {
+            return;
+        }
+        let end = offset + buf.len() as u64;
+        // Swallow every range that overlaps or touches the new one, the new bytes win where they overlap
+        let touching: Vec<u64> = self
+            .ranges
+            .range(..=end)
+            .rev()
+            .take_while(|(&start, bytes)| start + bytes.len() as u64 >= offset)
+            .map(|(&start, _)| start)
+            .collect();
+        let mut start = offset;
+        let mut merged_end = end;
+        for &range_start in touching.iter() {
+            start = start.min(range_start);
+            merged_end = merged_end.max(range_start + self.ranges[&range_start].len() as u64);
+        }
+        let mut merged = vec![0u8; (merged_end - start) as usize];
+        for range_start in touching {
+            let bytes = self.ranges.remove(&range_start).unwrap();
+            let from = (range_start - start) as usize;
+            merged[from..from + bytes.len()].copy_from_slice(&bytes);
+        }
+        let from = (offset - start) as usize;
+        merged[from..from + buf.len()].copy_from_slice(buf);
+        self.ranges.insert(start, merged);
+    }
+
+    pub fn remove(&mut self, len: usize, offset: u64) {
+        if len == 0 {
+            return;
+        }
+        let end = offset + len as u64;
+        let overlapping: Vec<u64> = self
+            .ranges
+            .range(..end)
+            .rev()
+            .take_while(|(&start, bytes)| start + bytes.len() as u64 > offset)
+            .map(|(&start, _)| start)
+            .collect();
+        for range_start in overlapping {
+            let bytes = self.ranges.remove(&range_start).unwrap();
+            let range_end = range_start + bytes.len() as u64;
+            if range_start < offset {
+                self.ranges.insert(
+                    range_start,
+                    bytes[..(offset - range_start) as usize].to_vec(),
+                );
+            }
+            if range_end > end {
+                self.ranges
+                    .insert(end, bytes[(end - range_start) as usize..].to_vec());
+            }
+        }
+    }
+
+    // Lays everything in `newer` over the top of this overlay
+    pub fn extend(&mut self, newer: &Overlay) {
+        for (&start, bytes) in newer.ranges.iter() {
+            self.insert(bytes, start);
+        }
+    }
+
+    pub fn patch(&self, buf: &mut [u8], offset: u64) {
+        let end = offset + buf.len() as u64;
+        let first = self
+            .ranges
+            .range(..=offset)
+            .next_back()
+            .map_or(offset, |(&start, _)| start);
+        for (&start, bytes) in self.ranges.range(first..end) {
+            let from = offset.max(start);
+            let to = end.min(start + bytes.len() as u64);
+            if from >= to {
+                continue;
+            }
+            buf[(from - offset) as usize..(to - offset) as usize]
+                .copy_from_slice(&bytes[(from - start) as usize..(to - start) as usize]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges(overlay: &Overlay) -> Vec<(u64, Vec<u8>)> {
+        overlay
+            .ranges()
+            .iter()
+            .map(|(&start, bytes)| (start, bytes.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn newer_bytes_win_where_writes_overlap() {
+        let mut overlay = Overlay::default();
+        overlay.insert(&[1, 1, 1, 1], 10);
+        overlay.insert(&[2, 2, 2, 2], 12);
+        overlay.insert(&[3, 3], 8);
+        assert_eq!(ranges(&overlay), vec![(8, vec![3, 3, 1, 1, 2, 2, 2, 2])]);
+        assert_eq!(overlay.dirty_bytes(), 8);
+    }
+
+    #[test]
+    fn touching_writes_merge_and_gaps_dont() {
+        let mut overlay = Overlay::default();
+        overlay.insert(&[1, 2], 0);
+        overlay.insert(&[3, 4], 2);
+        overlay.insert(&[5], 5);
+        assert_eq!(ranges(&overlay), vec![(0, vec![1, 2, 3, 4]), (5, vec![5])]);
+        // Filling the gap joins both sides
+        overlay.insert(&[9], 4);
+        assert_eq!(ranges(&overlay), vec![(0, vec![1, 2, 3, 4, 9, 5])]);
+    }
+
+    #[test]
+    fn a_write_can_swallow_or_sit_inside_another() {
+        let mut overlay = Overlay::default();
+        overlay.insert(&[1], 3);
+        overlay.insert(&[2], 6);
+        overlay.insert(&[7; 6], 2);
+        assert_eq!(ranges(&overlay), vec![(2, vec![7; 6])]);
+        overlay.insert(&[8, 8], 4);
+        assert_eq!(ranges(&overlay), vec![(2, vec![7, 7, 8, 8, 7, 7])]);
+        overlay.insert(&[], 100);
+        assert_eq!(overlay.dirty_bytes(), 6);
+    }
+
+    #[test]
+    fn removing_the_middle_splits_a_range() {
+        let mut overlay = Overlay::default();
+        overlay.insert(&[1, 2, 3, 4, 5, 6], 10);
+        overlay.remove(2, 12);
+        assert_eq!(ranges(&overlay), vec![(10, vec![1, 2]), (14, vec![5, 6])]);
+        // Trims that only clip the ends, or miss entirely, leave the rest alone
+        overlay.remove(3, 8);
+        overlay.remove(1, 15);
+        overlay.remove(4, 20);
+        overlay.remove(0, 14);
+        assert_eq!(ranges(&overlay), vec![(11, vec![2]), (14, vec![5])]);
+        overlay.remove(10, 10);
+        assert!(overlay.is_empty());
+    }
+
+    #[test]
+    fn overlaps_and_contains_stop_at_the_range_end() {
+        let mut overlay = Overlay::default();
+        overlay.insert(&[1, 2, 3], 10);
+        assert!(!overlay.contains(9));
+        assert!(overlay.contains(10));
+        assert!(overlay.contains(12));
+        assert!(!overlay.contains(13));
+        assert!(!overlay.overlaps(10, 0));
+        assert!(overlay.overlaps(1, 10));
+        assert!(overlay.overlaps(4, 7));
+        assert!(!overlay.overlaps(3, 7));
+        assert!(!overlay.overlaps(5, 13));
+    }
+
+    #[test]
+    fn patch_only_touches_the_overlapping_bytes() {
+        let mut overlay = Overlay::default();
+        overlay.insert(&[1, 2, 3], 2);
+        overlay.insert(&[7, 8], 8);
+        let mut buf = [0u8; 6];
+        overlay.patch(&mut buf, 3);
+        assert_eq!(buf, [2, 3, 0, 0, 0, 7]);
+        let mut buf = [9u8; 2];
+        overlay.patch(&mut buf, 5);
+        assert_eq!(buf, [9, 9]);
+        // Newer writes laid over older ones with extend win the same way insert does
+        let mut older = Overlay::default();
+        older.insert(&[5; 4], 0);
+        older.extend(&overlay);
+        assert_eq!(
+            ranges(&older),
+            vec![(0, vec![5, 5, 1, 2, 3]), (8, vec![7, 8])]
+        );
+    }
+}
@@ -0,0 +1,278 @@
+use std::{collections::BTreeMap, io, path::Path};
+
+use super::table;
+
+// Each range is stored as a little endian u64 start followed by a little endian u64 end
+const ENTRY_LEN: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Extent {
+    pub offset: u64,
+    pub len: u64,
+    pub written: bool,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct RangeMap {
+    // Start -> end (exclusive) of every range that has been written, never overlapping or touching
+    ranges: BTreeMap<u64, u64>,
+}
+
+impl RangeMap {
+    pub fn full(max_size: usize) -> Self {
+        let mut map = Self::default();
+        map.insert(max_size, 0);
+        map
+    }
+
+    pub fn written_bytes(&self) -> u64 {
+        self.ranges.iter().map(|(start, end)| end - start).sum()
+    }
+
+    pub fn contains(&self, address: u64) -> bool {
+        self.ranges
+            .range(..=address)
+            .next_back()
+            .is_some_and(|(_, &end)| address < end)
+    }
+
+    // The address of the nth written byte, counting through the written ranges in order
+    pub fn nth_written(&self, mut n: u64) -> Option<u64> {
+        for (&start, &end) in self.ranges.iter() {
+            if n < end - start {
+                return Some(start + n);
+            }
+            n -= end - start;
+        }
+        None
+    }
+
+    pub fn insert(&mut self, len: usize, offset: u64) {
+        if len == 0 {
+            return;
+        }
+        let mut start = offset;
+        let mut end = offset + len as u64;
+        // Swallow every range that overlaps or touches the new one
+        let touching: Vec<(u64, u64)> = self
+            .ranges
+            .range(..=end)
+            .rev()
+            .take_while(|(_, &range_end)| range_end >= start)
+            .map(|(&range_start, &range_end)| (range_start, range_end))
+            .collect();
+        for (range_start, range_end) in touching {
+            self.ranges.remove(&range_start);
+            start = start.min(range_start);
+            end = end.max(range_end);
+        }
+        self.ranges.insert(start, end);
+    }
+
+    pub fn remove(&mut self, len: usize, offset: u64) {
+        if len == 0 {
+            return;
+        }
+        let end = offset + len as u64;
+        let overlapping: Vec<(u64, u64)> = self
+            .ranges
+            .range(..end)
+            .rev()
+            .take_while(|(_, &range_end)| range_end > offset)
+            .map(|(&range_start, &range_end)| (range_start, range_end))
+            .collect();
+        for (range_start, range_end) in overlapping {
+            self.ranges.remove(&range_start);
+            if range_start < offset {
+                self.ranges.insert(range_start, offset);
+            }
+            if range_end > end {
+                self.ranges.insert(end, range_end);
+            }
+        }
+    }
+
+    // Splits [offset, offset + len) into alternating written and unwritten extents that cover all of it
+    pub fn extents(&self, len: usize, offset: u64) -> Vec<Extent> {
+        if len == 0 {
+            return Vec::new();
+        }
+        let end = offset + len as u64;
+        let mut extents = Vec::new();
+        let mut position = offset;
+        let first = self
+            .ranges
+            .range(..=offset)
+            .next_back()
+            .map_or(offset, |(&range_start, _)| range_start);
+        for (&range_start, &range_end) in self.ranges.range(first..end) {
+            if range_end <= position {
+                continue;
+            }
+            if range_start > position {
+                extents.push(Extent {
+                    offset: position,
+                    len: range_start - position,
+                    written: false,
+                });
+                position = range_start;
+            }
+            let written_end = range_end.min(end);
+            extents.push(Extent {
+                offset: position,
+                len: written_end - position,
+                written: true,
+            });
+            position = written_end;
+        }
+        if position < end {
+            extents.push(Extent {
+                offset: position,
+                len: end - position,
+                written: false,
+            });
+        }
+        extents
+    }
+
+    // Anything that was never written reads back as zero
+    pub fn zero_unwritten(&self, buf: &mut [u8], offset: u64) {
+        for extent in self.extents(buf.len(), offset) {
+            if !extent.written {
+                let from = (extent.offset - offset) as usize;
+                buf[from..from + extent.len as usize].fill(0);
+            }
+        }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut ranges = BTreeMap::new();
+        table::load::<ENTRY_LEN>(path, |start, end| {
+            ranges.insert(start, u64::from_le_bytes(end.try_into().unwrap()));
+        })?;
+        Ok(Self { ranges })
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        table::save::<ENTRY_LEN, _>(
+            path,
+            self.ranges
+                .iter()
+                .map(|(&start, &end)| (start, end.to_le_bytes())),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges(map: &RangeMap) -> Vec<(u64, u64)> {
+        map.ranges
+            .iter()
+            .map(|(&start, &end)| (start, end))
+            .collect()
+    }
+
+    fn extent(offset: u64, len: u64, written: bool) -> Extent {
+        Extent {
+            offset,
+            len,
+            written,
+        }
+    }
+
+    #[test]
+    fn overlapping_touching_and_contained_ranges_merge() {
+        let mut map = RangeMap::default();
+        map.insert(4, 10);
+        map.insert(4, 12);
+        assert_eq!(ranges(&map), vec![(10, 16)]);
+        map.insert(2, 16);
+        map.insert(2, 8);
+        assert_eq!(ranges(&map), vec![(8, 18)]);
+        map.insert(3, 9);
+        map.insert(0, 30);
+        assert_eq!(ranges(&map), vec![(8, 18)]);
+        map.insert(2, 20);
+        map.insert(20, 5);
+        assert_eq!(ranges(&map), vec![(5, 25)]);
+        assert_eq!(map.written_bytes(), 20);
+    }
+
+    #[test]
+    fn removing_splits_or_clips_ranges() {
+        let mut map = RangeMap::full(100);
+        map.remove(10, 40);
+        assert_eq!(ranges(&map), vec![(0, 40), (50, 100)]);
+        map.remove(20, 30);
+        map.remove(10, 95);
+        map.remove(0, 60);
+        assert_eq!(ranges(&map), vec![(0, 30), (50, 95)]);
+        map.remove(70, 20);
+        assert_eq!(ranges(&map), vec![(0, 20), (90, 95)]);
+        assert!(!map.contains(20));
+        assert!(map.contains(94));
+        assert_eq!(map.nth_written(19), Some(19));
+        assert_eq!(map.nth_written(20), Some(90));
+        assert_eq!(map.nth_written(25), None);
+    }
+
+    #[test]
+    fn extents_cover_the_request_exactly() {
+        let mut map = RangeMap::default();
+        map.insert(10, 10);
+        map.insert(10, 30);
+        assert_eq!(
+            map.extents(50, 0),
+            vec![
+                extent(0, 10, false),
+                extent(10, 10, true),
+                extent(20, 10, false),
+                extent(30, 10, true),
+                extent(40, 10, false),
+            ]
+        );
+        // Starting and ending inside written ranges clips them to the request
+        assert_eq!(
+            map.extents(20, 15),
+            vec![
+                extent(15, 5, true),
+                extent(20, 10, false),
+                extent(30, 5, true),
+            ]
+        );
+        // Starting and ending exactly on range boundaries
+        assert_eq!(
+            map.extents(30, 10),
+            vec![
+                extent(10, 10, true),
+                extent(20, 10, false),
+                extent(30, 10, true),
+            ]
+        );
+        assert_eq!(map.extents(5, 20), vec![extent(20, 5, false)]);
+        assert_eq!(map.extents(3, 12), vec![extent(12, 3, true)]);
+        assert_eq!(map.extents(0, 12), vec![]);
+    }
+
+    #[test]
+    fn extents_reach_the_end_of_the_drive() {
+        let map = RangeMap::full(64);
+        assert_eq!(map.extents(64, 0), vec![extent(0, 64, true)]);
+        assert_eq!(map.extents(1, 63), vec![extent(63, 1, true)]);
+        assert_eq!(
+            RangeMap::default().extents(64, 0),
+            vec![extent(0, 64, false)]
+        );
+    }
+
+    #[test]
+    fn unwritten_bytes_read_as_zero() {
+        let mut map = RangeMap::default();
+        map.insert(2, 3);
+        let mut buf = [9u8; 6];
+        map.zero_unwritten(&mut buf, 1);
+        assert_eq!(buf, [0, 0, 9, 9, 0, 0]);
+    }
+}
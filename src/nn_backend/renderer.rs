@@ -0,0 +1,113 @@
+use std::collections::BTreeMap;
+
+use burn::train::{
+    metric::MetricEntry,
+    renderer::{MetricState, MetricsRenderer, SelectedMetricsRenderer, TrainingProgress},
+    TrainingInterrupter,
+};
+
+// How a training run reports its progress
+#[derive(Clone, Copy, Debug)]
+pub enum RenderMode {
+    // Nothing at all
+    Quiet,
+    // One line per epoch handed to the sink
+    Log(fn(&str)),
+    // burn's own terminal UI, needs a tty
+    Tui,
+}
+
+impl RenderMode {
+    // One renderer lasts a whole training run, every round of it included
+    pub fn renderer(self) -> Box<dyn MetricsRenderer> {
+        match self {
+            RenderMode::Quiet => Box::new(QuietRenderer),
+            RenderMode::Log(sink) => Box::new(LogRenderer::new(sink)),
+            RenderMode::Tui => Box::new(SelectedMetricsRenderer::new(
+                TrainingInterrupter::new(),
+                None,
+            )),
+        }
+    }
+}
+
+pub struct QuietRenderer;
+
+impl MetricsRenderer for QuietRenderer {
+    fn update_train(&mut self, _state: MetricState) {}
+    fn update_valid(&mut self, _state: MetricState) {}
+    fn render_train(&mut self, _item: TrainingProgress) {}
+    fn render_valid(&mut self, _item: TrainingProgress) {}
+}
+
+pub struct LogRenderer {
+    sink: fn(&str),
+    // Metric name -> latest formatted value for the epoch in progress
+    train: BTreeMap<String, String>,
+    valid: BTreeMap<String, String>,
+    epoch: usize,
+    epoch_total: usize,
+}
+
+impl LogRenderer {
+    pub fn new(sink: fn(&str)) -> Self {
+        Self {
+            sink,
+            train: BTreeMap::new(),
+            valid: BTreeMap::new(),
+            epoch: 0,
+            epoch_total: 0,
+        }
+    }
+}
+
+// Numeric metrics are logged as their bare value, the formatted one is meant for the TUI
+fn entry(state: MetricState) -> (String, String) {
+    match state {
+        MetricState::Generic(MetricEntry {
+            name, formatted, ..
+        }) => (name, formatted),
+        MetricState::Numeric(MetricEntry { name, .. }, value) => (name, format!("{:.4}", value)),
+    }
+}
+
+fn metrics(split: &BTreeMap<String, String>) -> String {
+    split
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl MetricsRenderer for LogRenderer {
+    fn update_train(&mut self, state: MetricState) {
+        let (name, value) = entry(state);
+        self.train.insert(name, value);
+    }
+
+    fn update_valid(&mut self, state: MetricState) {
+        let (name, value) = entry(state);
+        self.valid.insert(name, value);
+    }
+
+    fn render_train(&mut self, item: TrainingProgress) {
+        self.epoch = item.epoch;
+        self.epoch_total = item.epoch_total;
+    }
+
+    // Validation runs last in every epoch, so once it's done the epoch is too
+    fn render_valid(&mut self, item: TrainingProgress) {
+        if item.progress.items_processed < item.progress.items_total {
+            return;
+        }
+        (self.sink)(&format!(
+            "training epoch {}/{} | train {} | valid {}",
+            self.epoch,
+            self.epoch_total,
+            metrics(&self.train),
+            metrics(&self.valid)
+        ));
+        self.train.clear();
+        self.valid.clear();
+    }
+}
@@ -0,0 +1,48 @@
+use std::collections::VecDeque;
+
+use rand::{distributions::WeightedIndex, prelude::Distribution, Rng};
+
+use super::{overlay::Overlay, ranges::RangeMap};
+
+// How many committed ranges are remembered for recency weighted replay
+pub const HISTORY_LEN: usize = 1024;
+
+// Picks up to `count` written addresses outside the batch, each equally likely
+pub fn uniform(rng: &mut impl Rng, written: &RangeMap, batch: &Overlay, count: usize) -> Vec<u64> {
+    let written_bytes = written.written_bytes();
+    if written_bytes == 0 {
+        return Vec::new();
+    }
+    (0..count)
+        .filter_map(|_| written.nth_written(rng.gen_range(0..written_bytes)))
+        .filter(|&address| !batch.contains(address))
+        .collect()
+}
+
+// Picks up to `count` addresses from recently committed ranges, the newer the range the likelier, falls back to uniform with no history
+pub fn recency(
+    rng: &mut impl Rng,
+    written: &RangeMap,
+    batch: &Overlay,
+    history: &VecDeque<(u64, usize)>,
+    count: usize,
+) -> Vec<u64> {
+    let Ok(ranges) = WeightedIndex::new(1..=history.len()) else {
+        return uniform(rng, written, batch, count);
+    };
+    (0..count)
+        .map(|_| {
+            let (offset, len) = history[ranges.sample(rng)];
+            offset + rng.gen_range(0..len as u64)
+        })
+        .filter(|&address| written.contains(address) && !batch.contains(address))
+        .collect()
+}
+
+// Picks `count` of the candidates with replacement, in proportion to their weights
+pub fn weighted(rng: &mut impl Rng, candidates: &[u64], weights: &[u32], count: usize) -> Vec<u64> {
+    let Ok(index) = WeightedIndex::new(weights) else {
+        return Vec::new();
+    };
+    (0..count).map(|_| candidates[index.sample(rng)]).collect()
+}
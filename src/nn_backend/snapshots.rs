@@ -0,0 +1,303 @@
+use std::{
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+};
+
+use super::interface::{NNError, JOURNAL_FILE};
+
+// Snapshots of a saved drive live in this subdirectory of it, one directory each
+pub const SNAPSHOT_SUBDIR: &str = "snapshots";
+// Names the snapshot the drive was last rolled back to, so a restart with the same rollback keeps what was written since
+const ROLLBACK_MARKER: &str = "rolled_back";
+
+// Where a snapshot of the drive saved in model_dir lives, whether or not it exists yet
+pub fn path(model_dir: &Path, name: &str) -> Result<PathBuf, NNError> {
+    // '@' separates the export from the snapshot in an export name, so it can't be part of one
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\', '@']) {
+        return Err(NNError::snapshot(
+            name,
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Snapshot name can't be used as a directory name",
+            ),
+        ));
+    }
+    Ok(model_dir.join(SNAPSHOT_SUBDIR).join(name))
+}
+
+// Names of every snapshot of the drive, oldest first
+pub fn list(model_dir: &Path) -> Result<Vec<String>, NNError> {
+    let snapshots_dir = model_dir.join(SNAPSHOT_SUBDIR);
+    if !snapshots_dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut snapshots = Vec::new();
+    for entry in fs::read_dir(&snapshots_dir).map_err(|e| NNError::snapshot("", e))? {
+        let entry = entry.map_err(|e| NNError::snapshot("", e))?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        // Half made snapshots are left behind by a crash mid-copy
+        if name.ends_with("-staged") || !entry.path().is_dir() {
+            continue;
+        }
+        let created = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .map_err(|e| NNError::snapshot(&name, e))?;
+        snapshots.push((created, name));
+    }
+    snapshots.sort();
+    Ok(snapshots.into_iter().map(|(_, name)| name).collect())
+}
+
+// Copies the weights and side tables as they were last saved, writes that are only journaled aren't part of it
+pub fn create(model_dir: &Path, name: &str) -> Result<(), NNError> {
+    let snapshot_dir = path(model_dir, name)?;
+    if snapshot_dir.exists() {
+        return Err(NNError::snapshot(
+            name,
+            io::Error::new(io::ErrorKind::AlreadyExists, "The snapshot already exists"),
+        ));
+    }
+    let mut staged = snapshot_dir.clone().into_os_string();
+    staged.push("-staged");
+    stage_snapshot(model_dir, Path::new(&staged), &snapshot_dir)
+        .map_err(|e| NNError::snapshot(name, e))
+}
+
+// Nothing is visible under the snapshot's name until every file is in it
+fn stage_snapshot(model_dir: &Path, staged: &Path, snapshot_dir: &Path) -> io::Result<()> {
+    if staged.exists() {
+        fs::remove_dir_all(staged)?;
+    }
+    fs::create_dir_all(staged)?;
+    copy_saved_files(model_dir, staged)?;
+    fs::rename(staged, snapshot_dir)?;
+    File::open(model_dir.join(SNAPSHOT_SUBDIR))?.sync_all()
+}
+
+pub fn delete(model_dir: &Path, name: &str) -> Result<(), NNError> {
+    fs::remove_dir_all(path(model_dir, name)?).map_err(|e| NNError::snapshot(name, e))?;
+    // A new snapshot taken under the same name can be rolled back to again
+    if rolled_back_to(model_dir, name) {
+        fs::remove_file(model_dir.join(ROLLBACK_MARKER)).map_err(|e| NNError::snapshot(name, e))?;
+    }
+    Ok(())
+}
+
+// Puts the saved drive back the way it was when the snapshot was taken, anything written since is thrown away journal and all
+// Only once per snapshot, false if the drive was already rolled back to it
+pub fn rollback(model_dir: &Path, name: &str) -> Result<bool, NNError> {
+    let snapshot_dir = path(model_dir, name)?;
+    if !snapshot_dir.is_dir() {
+        return Err(NNError::snapshot(
+            name,
+            io::Error::from_raw_os_error(libc::ENOENT),
+        ));
+    }
+    if rolled_back_to(model_dir, name) {
+        return Ok(false);
+    }
+    restore_snapshot(&snapshot_dir, model_dir, name).map_err(|e| NNError::snapshot(name, e))?;
+    Ok(true)
+}
+
+fn rolled_back_to(model_dir: &Path, name: &str) -> bool {
+    fs::read_to_string(model_dir.join(ROLLBACK_MARKER)).is_ok_and(|marker| marker == name)
+}
+
+fn restore_snapshot(snapshot_dir: &Path, model_dir: &Path, name: &str) -> io::Result<()> {
+    // Side tables the drive picked up after the snapshot have to go too, the snapshot is copied over whatever's left
+    for saved in saved_files(model_dir)? {
+        if !snapshot_dir.join(&saved).exists() {
+            fs::remove_file(model_dir.join(&saved))?;
+        }
+    }
+    copy_saved_files(snapshot_dir, model_dir)?;
+    let journal = model_dir.join(JOURNAL_FILE);
+    if journal.exists() {
+        fs::remove_file(journal)?;
+    }
+    let staged = model_dir.join(format!("{ROLLBACK_MARKER}-staged"));
+    fs::write(&staged, name)?;
+    File::open(&staged)?.sync_all()?;
+    fs::rename(&staged, model_dir.join(ROLLBACK_MARKER))?;
+    File::open(model_dir)?.sync_all()
+}
+
+// Starts a new drive off the parent's last save, sharing its files until the new drive's first save renames its own over them
+pub fn fork(parent_dir: &Path, child_dir: &Path) -> Result<(), NNError> {
+    let mut staged = child_dir.as_os_str().to_os_string();
+    staged.push("-staged");
+    stage_fork(parent_dir, Path::new(&staged), child_dir).map_err(|e| NNError::fork(child_dir, e))
+}
+
+fn stage_fork(parent_dir: &Path, staged: &Path, child_dir: &Path) -> io::Result<()> {
+    if staged.exists() {
+        fs::remove_dir_all(staged)?;
+    }
+    fs::create_dir_all(staged)?;
+    for name in saved_files(parent_dir)? {
+        // Saves never write a file in place, so a hard link is as good as a copy, it just can't cross filesystems
+        if fs::hard_link(parent_dir.join(&name), staged.join(&name)).is_err() {
+            fs::copy(parent_dir.join(&name), staged.join(&name))?;
+            File::open(staged.join(&name))?.sync_all()?;
+        }
+    }
+    // Writes the parent acknowledged but never saved carry over too, copied since the parent keeps appending to its own
+    let journal = parent_dir.join(JOURNAL_FILE);
+    if journal.exists() {
+        fs::copy(&journal, staged.join(JOURNAL_FILE))?;
+        File::open(staged.join(JOURNAL_FILE))?.sync_all()?;
+    }
+    File::open(staged)?.sync_all()?;
+    fs::rename(staged, child_dir)?;
+    match child_dir.parent() {
+        Some(parent) => File::open(parent)?.sync_all(),
+        None => Ok(()),
+    }
+}
+
+// Every file a save leaves at the top of the drive's directory, the journal, the rollback marker and any leftover staged files aside
+fn saved_files(dir: &Path) -> io::Result<Vec<String>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type()?.is_file()
+            && name != JOURNAL_FILE
+            && name != ROLLBACK_MARKER
+            && !name.contains("-staged")
+        {
+            files.push(name);
+        }
+    }
+    Ok(files)
+}
+
+// Each file is staged and renamed into place, so a crash part way leaves every file whole
+fn copy_saved_files(from: &Path, to: &Path) -> io::Result<()> {
+    for name in saved_files(from)? {
+        let staged = to.join(format!("{name}-staged"));
+        fs::copy(from.join(&name), &staged)?;
+        File::open(&staged)?.sync_all()?;
+        fs::rename(&staged, to.join(&name))?;
+    }
+    File::open(to)?.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    // A directory laid out like a saved drive, each file holding its own name
+    fn saved_drive(name: &str, files: &[&str]) -> PathBuf {
+        let dir = env::temp_dir().join(format!("snapshots-{}-{}", process::id(), name));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        for file in files {
+            fs::write(dir.join(file), file).unwrap();
+        }
+        dir
+    }
+
+    fn files(dir: &Path) -> Vec<String> {
+        let mut files = saved_files(dir).unwrap();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn names_that_arent_plain_directory_names_are_rejected() {
+        let dir = Path::new("drive");
+        for name in ["", ".hidden", "..", "a/b", "a\\b", "export@snap"] {
+            let error = path(dir, name).unwrap_err();
+            assert_eq!(error.errno(), libc::EINVAL, "{name:?}");
+        }
+        assert_eq!(
+            path(dir, "before-upgrade").unwrap(),
+            dir.join(SNAPSHOT_SUBDIR).join("before-upgrade")
+        );
+    }
+
+    #[test]
+    fn create_copies_only_what_was_saved() {
+        let dir = saved_drive("create", &["config.json", "model.mpk", JOURNAL_FILE]);
+        create(&dir, "first").unwrap();
+        let snapshot = path(&dir, "first").unwrap();
+        assert_eq!(files(&snapshot), ["config.json", "model.mpk"]);
+        assert_eq!(create(&dir, "first").unwrap_err().errno(), libc::EEXIST);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn list_skips_half_made_snapshots() {
+        let dir = saved_drive("list", &["config.json"]);
+        create(&dir, "first").unwrap();
+        create(&dir, "second").unwrap();
+        fs::create_dir_all(dir.join(SNAPSHOT_SUBDIR).join("third-staged")).unwrap();
+        fs::write(dir.join(SNAPSHOT_SUBDIR).join("stray"), "").unwrap();
+        let mut names = list(&dir).unwrap();
+        names.sort();
+        assert_eq!(names, ["first", "second"]);
+        delete(&dir, "first").unwrap();
+        assert_eq!(list(&dir).unwrap(), ["second"]);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn rollback_puts_back_exactly_what_was_snapshotted() {
+        let dir = saved_drive("rollback", &["config.json", "model.mpk"]);
+        create(&dir, "first").unwrap();
+        // Saved after the snapshot, including a side table it never had
+        fs::write(dir.join("model.mpk"), "retrained").unwrap();
+        fs::write(dir.join("corrections.bin"), "").unwrap();
+        fs::write(dir.join(JOURNAL_FILE), "unsaved").unwrap();
+        assert!(rollback(&dir, "first").unwrap());
+        assert_eq!(files(&dir), ["config.json", "model.mpk"]);
+        assert_eq!(
+            fs::read_to_string(dir.join("model.mpk")).unwrap(),
+            "model.mpk"
+        );
+        assert!(!dir.join(JOURNAL_FILE).exists());
+        // A restart with the same rollback keeps what was written since
+        fs::write(dir.join("model.mpk"), "written since").unwrap();
+        assert!(!rollback(&dir, "first").unwrap());
+        assert_eq!(
+            fs::read_to_string(dir.join("model.mpk")).unwrap(),
+            "written since"
+        );
+        // Until the snapshot is deleted and taken again
+        delete(&dir, "first").unwrap();
+        create(&dir, "first").unwrap();
+        assert!(rollback(&dir, "first").unwrap());
+        assert_eq!(rollback(&dir, "missing").unwrap_err().errno(), libc::ENOENT);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn fork_starts_from_the_parents_save_and_journal() {
+        let parent = saved_drive("fork-parent", &["config.json", "model.mpk", JOURNAL_FILE]);
+        create(&parent, "first").unwrap();
+        let child = parent.with_file_name(format!("snapshots-{}-fork-child", process::id()));
+        fs::remove_dir_all(&child).ok();
+        fork(&parent, &child).unwrap();
+        assert_eq!(files(&child), ["config.json", "model.mpk"]);
+        assert_eq!(
+            fs::read_to_string(child.join(JOURNAL_FILE)).unwrap(),
+            JOURNAL_FILE
+        );
+        // Snapshots stay with the parent
+        assert!(list(&child).unwrap().is_empty());
+        // The child's journal is its own, nothing it appends shows up in the parent's
+        fs::write(child.join(JOURNAL_FILE), "child").unwrap();
+        assert_eq!(
+            fs::read_to_string(parent.join(JOURNAL_FILE)).unwrap(),
+            JOURNAL_FILE
+        );
+        fs::remove_dir_all(&parent).ok();
+        fs::remove_dir_all(&child).ok();
+    }
+}
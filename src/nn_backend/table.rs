@@ -0,0 +1,80 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+// Side tables are saved as a run of N byte entries and nothing else, each a little endian u64 key followed by its value
+
+pub fn load<const N: usize>(path: &Path, mut entry: impl FnMut(u64, &[u8])) -> io::Result<()> {
+    let file = File::open(path)?;
+    let len = file.metadata()?.len();
+    // Saves are staged and renamed, so a partial entry means the file was damaged rather than cut short mid-save
+    if len % N as u64 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{len} bytes isn't a whole number of {N} byte entries"),
+        ));
+    }
+    let mut reader = BufReader::new(file);
+    let mut bytes = [0u8; N];
+    for _ in 0..len / N as u64 {
+        reader.read_exact(&mut bytes)?;
+        entry(
+            u64::from_le_bytes(bytes[..8].try_into().unwrap()),
+            &bytes[8..],
+        );
+    }
+    Ok(())
+}
+
+pub fn save<const N: usize, V: AsRef<[u8]>>(
+    path: &Path,
+    entries: impl Iterator<Item = (u64, V)>,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    let mut bytes = [0u8; N];
+    for (key, value) in entries {
+        bytes[..8].copy_from_slice(&key.to_le_bytes());
+        bytes[8..].copy_from_slice(value.as_ref());
+        writer.write_all(&bytes)?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+
+    #[test]
+    fn entries_round_trip() {
+        let path = env::temp_dir().join(format!("table-{}.bin", process::id()));
+        let entries = [(3u64, 7u32), (u64::MAX, 0), (9, u32::MAX)];
+        save::<12, _>(
+            &path,
+            entries
+                .iter()
+                .map(|&(key, value)| (key, value.to_le_bytes())),
+        )
+        .unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), 36);
+        let mut loaded = Vec::new();
+        load::<12>(&path, |key, value| {
+            loaded.push((key, u32::from_le_bytes(value.try_into().unwrap())));
+        })
+        .unwrap();
+        assert_eq!(loaded, entries);
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn partial_entry_is_rejected() {
+        let path = env::temp_dir().join(format!("table-partial-{}.bin", process::id()));
+        fs::write(&path, [0u8; 13]).unwrap();
+        let error = load::<12>(&path, |_, _| panic!("no entry should be read")).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(&path).ok();
+    }
+}
@@ -1,6 +1,4 @@
-use burn::backend::wgpu::AutoGraphicsApi;
 use burn::backend::Autodiff;
-use burn::backend::Wgpu;
 use burn::config::Config;
 use burn::data::dataloader::batcher::Batcher;
 use burn::data::dataloader::DataLoaderBuilder;
@@ -11,22 +9,18 @@ use burn::record::CompactRecorder;
 use burn::record::Recorder;
 use burn::tensor::backend::{AutodiffBackend, Backend};
 use burn::train::metric::LossMetric;
-use burn::train::LearnerBuilder;
+use burn::train::{LearnerBuilder, RegressionOutput};
 
+#[allow(dead_code)] // Standalone training harness, not used by the drive
+#[allow(clippy::clone_on_copy)] // Only some backends have a Copy device
 fn main() {
     println!("Hello World!");
-    type MyBackend = Wgpu<AutoGraphicsApi, f32, i32>;
+    type MyBackend = crate::DriveBackend;
     type MyAutoDiffBackend = Autodiff<MyBackend>;
-    let device = burn::backend::wgpu::WgpuDevice::default();
+    let device = <MyBackend as Backend>::Device::default();
     train::<MyAutoDiffBackend>(
         "/tmp/guide",
-        TrainingConfig::new(
-            crate::model::ModelConfig {
-                input_size: 64,
-                output_size: 1,
-            },
-            AdamConfig::new(),
-        ),
+        TrainingConfig::new(crate::model::ModelConfig::new(64, 1), AdamConfig::new()),
         device.clone(),
     );
     for i in 0..100 {
@@ -42,6 +36,7 @@ fn main() {
 pub struct TrainingConfig {
     pub model: crate::model::ModelConfig,
     pub optimizer: AdamConfig,
+    // Most epochs a training run may take
     #[config(default = 20)]
     pub num_epochs: usize,
     #[config(default = 64)]
@@ -52,38 +47,75 @@ pub struct TrainingConfig {
     pub seed: u64,
     #[config(default = 1.0e-2)]
     pub learning_rate: f64,
+    // Where the learner writes checkpoints and logs, None uses a scratch directory for the process
+    pub artifact_dir: Option<String>,
+    // How many epoch checkpoints a training run keeps, 0 turns checkpointing off and None keeps DEFAULT_CHECKPOINTS
+    pub checkpoints: Option<usize>,
+    // A run stops once the train loss drops below this, even if some bytes still read back wrong
+    pub loss_threshold: Option<f32>,
+    // Longest a run may train in seconds, only checked between rounds so it can overshoot by one
+    pub time_limit: Option<u64>,
+    // Epochs trained between checks of the stopping conditions, None keeps DEFAULT_CHECK_EVERY
+    pub check_every: Option<usize>,
+    // Replayed addresses per dirty byte in an incremental run, None retrains the whole drive every time
+    pub replay_ratio: Option<f64>,
+    // How replayed addresses are picked, None samples uniformly
+    pub replay_sampling: Option<ReplaySampling>,
 }
 
+#[derive(Config, Debug, Copy, PartialEq, Eq)]
+pub enum ReplaySampling {
+    // Every written address is as likely as any other
+    Uniform,
+    // Addresses from recently committed writes are likelier
+    Recency,
+    // Addresses the weights get most wrong are likelier, needs corrections since without them the weights are all there is to be wrong about
+    Error,
+}
+
+pub const DEFAULT_CHECKPOINTS: usize = 2;
+// Most replayed addresses per dirty byte, past this an incremental run costs more than retraining the whole drive
+pub const MAX_REPLAY_RATIO: f64 = 64.0;
+pub const DEFAULT_CHECK_EVERY: usize = 5;
+
 impl Default for TrainingConfig {
     fn default() -> Self {
         Self {
-            model: crate::model::ModelConfig {
-                input_size: 64,
-                output_size: 1,
-            },
+            model: crate::model::ModelConfig::new(64, 1),
             optimizer: AdamConfig::new(),
             num_epochs: 20,
             batch_size: 64,
             num_workers: 4,
             seed: 42,
             learning_rate: 1.0e-2,
+            artifact_dir: None,
+            checkpoints: None,
+            loss_threshold: None,
+            time_limit: None,
+            check_every: None,
+            replay_ratio: None,
+            replay_sampling: None,
         }
     }
 }
 
+#[allow(dead_code)]
 fn create_artifact_dir(artifact_dir: &str) {
-    std::fs::remove_dir_all(artifact_dir).ok();
+    // Left as it is if it's already there, it may be shared with another run
     std::fs::create_dir_all(artifact_dir).ok();
 }
 
+#[allow(dead_code)]
 pub fn train<B: AutodiffBackend>(artifact_dir: &str, config: TrainingConfig, device: B::Device) {
     create_artifact_dir(artifact_dir);
     config
         .save(format!("{artifact_dir}/config.json"))
         .expect("Config should be saved successfully");
     B::seed(config.seed);
-    let batcher_train = super::batcher::InternalBatcher::<B>::new(device.clone());
-    let batcher_valid = super::batcher::InternalBatcher::<B::InnerBackend>::new(device.clone());
+    let head = config.model.head();
+    let batcher_train = super::batcher::InternalBatcher::<B>::new(device.clone(), head);
+    let batcher_valid =
+        super::batcher::InternalBatcher::<B::InnerBackend>::new(device.clone(), head);
     let dataloader_train = DataLoaderBuilder::new(batcher_train)
         .batch_size(config.batch_size)
         .shuffle(config.seed)
@@ -94,7 +126,11 @@ pub fn train<B: AutodiffBackend>(artifact_dir: &str, config: TrainingConfig, dev
         .shuffle(config.seed)
         .num_workers(config.num_workers)
         .build(super::dataloader::CustomDataset::old_new());
-    let learner = LearnerBuilder::new(artifact_dir)
+    // The model can train as a regression or a classification, the harness only ever regresses
+    let learner =
+        LearnerBuilder::<B, RegressionOutput<B>, RegressionOutput<B::InnerBackend>, _, _, _>::new(
+            artifact_dir,
+        )
         .metric_train_numeric(LossMetric::new())
         .metric_valid_numeric(LossMetric::new())
         .with_file_checkpointer(CompactRecorder::new())
@@ -112,6 +148,7 @@ pub fn train<B: AutodiffBackend>(artifact_dir: &str, config: TrainingConfig, dev
         .expect("Trained Model should be saved");
 }
 
+#[allow(dead_code)]
 pub fn infer<B: Backend>(artifact_dir: &str, device: B::Device, item: super::dataloader::DataItem) {
     let config = TrainingConfig::load(format!("{artifact_dir}/config.json"))
         .expect("Config should exist for the model");
@@ -120,8 +157,8 @@ pub fn infer<B: Backend>(artifact_dir: &str, device: B::Device, item: super::dat
         .expect("Trained model should exist");
     let model = config.model.init::<B>(&device).load_record(record);
     let label = item.value;
-    let batcher = super::batcher::InternalBatcher::new(device);
+    let batcher = super::batcher::InternalBatcher::new(device, model.head());
     let batch = batcher.batch(vec![item]);
-    let output = model.forward(batch.addresses);
-    println!("Predicted {} | Expected {}", output.into_data(), label);
+    let output = model.decode(model.forward(batch.addresses));
+    println!("Predicted {} | Expected {}", output[0], label);
 }